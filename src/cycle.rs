@@ -0,0 +1,172 @@
+use std::{collections::HashMap, hash::Hash};
+
+// A sequence x0, x1 = step(x0), ... that first repeats as x[prefix + period] = x[prefix].
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Cycle {
+    pub prefix: usize,
+    pub period: usize,
+}
+
+impl Cycle {
+    // The smallest index holding the same state as index n.
+    pub fn reduce(&self, n: usize) -> usize {
+        if n < self.prefix {
+            n
+        } else {
+            self.prefix + (n - self.prefix) % self.period
+        }
+    }
+}
+
+// Floyd's tortoise and hare: constant memory, about 3 * (prefix + period) steps.
+pub fn floyd<T: Eq + Clone>(start: &T, mut step: impl FnMut(&T) -> T) -> Cycle {
+    let mut tortoise = step(start);
+    let mut hare = step(&tortoise);
+    while tortoise != hare {
+        tortoise = step(&tortoise);
+        hare = step(&hare);
+        hare = step(&hare);
+    }
+
+    let mut prefix = 0;
+    tortoise = start.clone();
+    while tortoise != hare {
+        tortoise = step(&tortoise);
+        hare = step(&hare);
+        prefix += 1;
+    }
+
+    let mut period = 1;
+    hare = step(&tortoise);
+    while tortoise != hare {
+        hare = step(&hare);
+        period += 1;
+    }
+    Cycle { prefix, period }
+}
+
+// Brent's algorithm: constant memory, and usually fewer steps than Floyd's.
+pub fn brent<T: Eq + Clone>(start: &T, mut step: impl FnMut(&T) -> T) -> Cycle {
+    let mut power = 1;
+    let mut period = 1;
+    let mut tortoise = start.clone();
+    let mut hare = step(start);
+    while tortoise != hare {
+        if power == period {
+            tortoise = hare.clone();
+            power *= 2;
+            period = 0;
+        }
+        hare = step(&hare);
+        period += 1;
+    }
+
+    tortoise = start.clone();
+    hare = start.clone();
+    for _ in 0..period {
+        hare = step(&hare);
+    }
+    let mut prefix = 0;
+    while tortoise != hare {
+        tortoise = step(&tortoise);
+        hare = step(&hare);
+        prefix += 1;
+    }
+    Cycle { prefix, period }
+}
+
+// Every state up to the first repeat, found by hashing: prefix + period steps exactly, and any
+// later state can be read back without stepping again.
+#[derive(Clone, Debug)]
+pub struct History<T> {
+    states: Vec<T>,
+    cycle: Cycle,
+}
+
+impl<T: Eq + Hash + Clone> History<T> {
+    pub fn detect(start: T, mut step: impl FnMut(&T) -> T) -> Self {
+        let mut seen = HashMap::new();
+        let mut states = Vec::new();
+        let mut state = start;
+        let prefix = loop {
+            if let Some(&first) = seen.get(&state) {
+                break first;
+            }
+            seen.insert(state.clone(), states.len());
+            let next = step(&state);
+            states.push(state);
+            state = next;
+        };
+        let period = states.len() - prefix;
+        History {
+            states,
+            cycle: Cycle { prefix, period },
+        }
+    }
+}
+
+impl<T> History<T> {
+    pub fn cycle(&self) -> Cycle {
+        self.cycle
+    }
+
+    // The states before the cycle and then one full period of it.
+    pub fn states(&self) -> &[T] {
+        &self.states
+    }
+
+    pub fn nth(&self, n: usize) -> &T {
+        &self.states[self.cycle.reduce(n)]
+    }
+}
+
+// The state after n steps, using Brent's algorithm to skip whole periods.
+pub fn nth_state<T: Eq + Clone>(start: &T, mut step: impl FnMut(&T) -> T, n: usize) -> T {
+    let cycle = brent(start, &mut step);
+    let mut state = start.clone();
+    for _ in 0..cycle.reduce(n) {
+        state = step(&state);
+    }
+    state
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detectors_agree() {
+        // x -> x^2 + 1 mod m gives rho shapes with all sorts of tails and loops.
+        for m in 1..200u64 {
+            for start in [0, 1, 2, 7] {
+                let step = |x: &u64| (x * x + 1) % m;
+                let start = start % m;
+                let history = History::detect(start, step);
+                let cycle = history.cycle();
+                assert_eq!(floyd(&start, step), cycle, "m = {m}");
+                assert_eq!(brent(&start, step), cycle, "m = {m}");
+
+                let mut state = start;
+                for n in 0..3 * (cycle.prefix + cycle.period) {
+                    assert_eq!(*history.nth(n), state);
+                    assert_eq!(nth_state(&start, step, n), state);
+                    state = step(&state);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn pure_cycle() {
+        let history = History::detect(0, |x| (x + 1) % 5);
+        assert_eq!(
+            history.cycle(),
+            Cycle {
+                prefix: 0,
+                period: 5
+            }
+        );
+        assert_eq!(history.states(), [0, 1, 2, 3, 4]);
+        assert_eq!(*history.nth(1_000_000_001), 1);
+    }
+}
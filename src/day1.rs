@@ -1,72 +1,217 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 
+use anyhow::{anyhow, Result};
 use aoc_runner_derive::{aoc, aoc_generator};
 #[aoc_generator(day1)]
 fn parse(input: &str) -> Vec<String> {
     input.lines().map(|x| x.to_string()).collect()
 }
 
-#[aoc(day1, part1)]
-fn part1(input: &[String]) -> i32 {
-    input
-        .into_iter()
-        .map(|s| {
-            let digits = s.chars().filter(|x| x.is_numeric()).collect::<Vec<_>>();
-            let first = *digits.first().unwrap();
-            let last = *digits.last().unwrap();
-            format!("{first}{last}").parse::<i32>().unwrap()
-        })
-        .sum()
+const NUMERALS: [(&str, u32); 10] = [
+    ("0", 0),
+    ("1", 1),
+    ("2", 2),
+    ("3", 3),
+    ("4", 4),
+    ("5", 5),
+    ("6", 6),
+    ("7", 7),
+    ("8", 8),
+    ("9", 9),
+];
+
+pub const ENGLISH_DIGITS: [(&str, u32); 9] = [
+    ("one", 1),
+    ("two", 2),
+    ("three", 3),
+    ("four", 4),
+    ("five", 5),
+    ("six", 6),
+    ("seven", 7),
+    ("eight", 8),
+    ("nine", 9),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Match {
+    pub start: usize,
+    pub end: usize,
+    pub value: u32,
 }
 
-#[aoc(day1, part2)]
-fn part2(input: &[String]) -> i32 {
-    let replacements = HashMap::from([
-        ("one", 1),
-        ("two", 2),
-        ("three", 3),
-        ("four", 4),
-        ("five", 5),
-        ("six", 6),
-        ("seven", 7),
-        ("eight", 8),
-        ("nine", 9),
-    ]);
-    let replacements_reversed = replacements
-        .iter()
-        .map(|(k, v)| (k.chars().rev().collect::<String>(), v))
-        .collect::<HashMap<_, _>>();
-
-    input
-        .iter()
-        .map(|line| {
-            let first = (|| {
-                for (i, c) in line.char_indices() {
-                    if c.is_numeric() {
-                        return format!("{c}");
-                    }
-                    if let Some(k) = replacements.keys().find(|k| line[..(i + 1)].ends_with(**k)) {
-                        return format!("{}", replacements.get(k).unwrap());
-                    }
+// An Aho-Corasick automaton over chars. Every state carries the (length, value) of each word
+// ending there, including those reached through fail links, so overlapping words like
+// "eightwo" are all reported from a single left-to-right scan.
+#[derive(Debug, Clone)]
+pub struct DigitMatcher {
+    goto: Vec<HashMap<char, usize>>,
+    fail: Vec<usize>,
+    outputs: Vec<Vec<(usize, u32)>>,
+}
+
+impl DigitMatcher {
+    pub fn new<'a>(words: impl IntoIterator<Item = (&'a str, u32)>) -> Self {
+        let mut matcher = DigitMatcher {
+            goto: vec![HashMap::new()],
+            fail: vec![0],
+            outputs: vec![Vec::new()],
+        };
+        for (word, value) in words {
+            matcher.insert(word, value);
+        }
+        matcher.build_fail_links();
+        matcher
+    }
+
+    pub fn numerals() -> Self {
+        Self::new(NUMERALS)
+    }
+
+    pub fn with_words<'a>(words: impl IntoIterator<Item = (&'a str, u32)>) -> Self {
+        Self::new(NUMERALS.into_iter().chain(words))
+    }
+
+    fn insert(&mut self, word: &str, value: u32) {
+        assert!(!word.is_empty(), "cannot match an empty word");
+        let mut state = 0;
+        let mut len = 0;
+        for c in word.chars() {
+            len += 1;
+            state = match self.goto[state].get(&c) {
+                Some(&next) => next,
+                None => {
+                    self.goto.push(HashMap::new());
+                    self.fail.push(0);
+                    self.outputs.push(Vec::new());
+                    let next = self.goto.len() - 1;
+                    self.goto[state].insert(c, next);
+                    next
                 }
-                unreachable!()
-            })();
-            let line = line.chars().rev().collect::<String>();
-            let last = (|| {
-                for (i, c) in line.char_indices() {
-                    if c.is_numeric() {
-                        return format!("{c}");
-                    }
-                    if let Some(k) = replacements_reversed
-                        .keys()
-                        .find(|k| line[..(i + 1)].ends_with(&**k))
-                    {
-                        return format!("{}", replacements_reversed.get(k).unwrap());
-                    }
+            };
+        }
+        self.outputs[state].retain(|(l, _)| *l != len);
+        self.outputs[state].push((len, value));
+    }
+
+    fn build_fail_links(&mut self) {
+        let mut queue = self.goto[0].values().copied().collect::<VecDeque<_>>();
+        while let Some(state) = queue.pop_front() {
+            let edges = self.goto[state]
+                .iter()
+                .map(|(c, next)| (*c, *next))
+                .collect::<Vec<_>>();
+            for (c, next) in edges {
+                let mut f = self.fail[state];
+                while f != 0 && !self.goto[f].contains_key(&c) {
+                    f = self.fail[f];
                 }
-                unreachable!()
-            })();
-            format!("{first}{last}").parse::<i32>().unwrap()
+                let target = self.goto[f].get(&c).copied().unwrap_or(0);
+                self.fail[next] = target;
+                let inherited = self.outputs[target].clone();
+                self.outputs[next].extend(inherited);
+                queue.push_back(next);
+            }
+        }
+    }
+
+    fn step(&self, mut state: usize, c: char) -> usize {
+        loop {
+            if let Some(&next) = self.goto[state].get(&c) {
+                return next;
+            }
+            if state == 0 {
+                return 0;
+            }
+            state = self.fail[state];
+        }
+    }
+
+    // Positions are char indices, `end` exclusive.
+    pub fn matches<'a>(&'a self, line: &'a str) -> impl Iterator<Item = Match> + 'a {
+        line.chars()
+            .scan(0, move |state, c| {
+                *state = self.step(*state, c);
+                Some(*state)
+            })
+            .enumerate()
+            .flat_map(move |(i, state)| {
+                self.outputs[state].iter().map(move |&(len, value)| Match {
+                    start: i + 1 - len,
+                    end: i + 1,
+                    value,
+                })
+            })
+    }
+
+    // The leftmost-starting and rightmost-ending matches, preferring the longer word on ties.
+    pub fn first_and_last(&self, line: &str) -> Option<(Match, Match)> {
+        self.matches(line).fold(None, |acc, m| match acc {
+            None => Some((m, m)),
+            Some((first, last)) => {
+                let first = if (m.start, first.end) < (first.start, m.end) {
+                    m
+                } else {
+                    first
+                };
+                let last = if (m.end, last.start) > (last.end, m.start) {
+                    m
+                } else {
+                    last
+                };
+                Some((first, last))
+            }
         })
-        .sum()
+    }
+
+    pub fn calibration_value(&self, line: &str) -> Result<u32> {
+        let (first, last) = self
+            .first_and_last(line)
+            .ok_or_else(|| anyhow!("no digit found in line {line:?}"))?;
+        Ok(format!("{}{}", first.value, last.value).parse()?)
+    }
+
+    pub fn calibration_sum(&self, input: &[String]) -> Result<u32> {
+        input.iter().map(|line| self.calibration_value(line)).sum()
+    }
+}
+
+#[aoc(day1, part1)]
+fn part1(input: &[String]) -> Result<u32> {
+    DigitMatcher::numerals().calibration_sum(input)
+}
+
+#[aoc(day1, part2)]
+fn part2(input: &[String]) -> Result<u32> {
+    DigitMatcher::with_words(ENGLISH_DIGITS).calibration_sum(input)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn part2_sample() {
+        assert_eq!(
+            part2(&parse(
+                "two1nine
+eightwothree
+abcone2threexyz
+xtwone3four
+4nineeightseven2
+zoneight234
+7pqrstsixteen
+"
+            ))
+            .unwrap(),
+            281
+        );
+    }
+
+    #[test]
+    fn overlapping_and_custom_words() {
+        let matcher = DigitMatcher::with_words(ENGLISH_DIGITS.into_iter().chain([("ten", 10)]));
+        assert_eq!(matcher.calibration_value("eightwo").unwrap(), 82);
+        assert_eq!(matcher.calibration_value("xtenonex").unwrap(), 101);
+        assert!(matcher.calibration_value("nothing here").is_err());
+    }
 }
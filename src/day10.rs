@@ -1,10 +1,11 @@
 use std::collections::{HashMap, HashSet};
 
+use anyhow::{anyhow, bail, ensure, Result};
 use aoc_runner_derive::{aoc, aoc_generator};
 use itertools::{Itertools, MinMaxResult};
 
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
-enum Dir {
+pub enum Dir {
     N,
     E,
     S,
@@ -12,13 +13,13 @@ enum Dir {
 }
 
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
-enum Tile {
+pub enum Tile {
     Ground,
     Start,
     Pipe([Dir; 2]),
 }
 
-type Coords = (i64, i64);
+pub type Coords = (i64, i64);
 
 #[aoc_generator(day10)]
 fn parse(input: &str) -> HashMap<Coords, Tile> {
@@ -63,67 +64,109 @@ fn apply((r, c): Coords, dir: Dir) -> Coords {
     }
 }
 
-fn neighborhood(input: &HashMap<Coords, Tile>, loc: Coords) -> Vec<Coords> {
+fn opposite(dir: Dir) -> Dir {
+    match dir {
+        Dir::N => Dir::S,
+        Dir::E => Dir::W,
+        Dir::S => Dir::N,
+        Dir::W => Dir::E,
+    }
+}
+
+fn neighborhood(input: &HashMap<Coords, Tile>, start: &StartShape, loc: Coords) -> Vec<Coords> {
     let tile = *input.get(&loc).unwrap();
     match tile {
         Tile::Ground => vec![],
-        Tile::Start => [
-            apply(loc, Dir::N),
-            apply(loc, Dir::E),
-            apply(loc, Dir::S),
-            apply(loc, Dir::W),
-        ]
-        .into_iter()
-        .filter(|next_loc| {
-            input.contains_key(next_loc) && neighborhood(input, *next_loc).contains(&loc)
-        })
-        .collect(),
-        Tile::Pipe([a, b]) => [apply(loc, a), apply(loc, b)]
+        Tile::Start | Tile::Pipe(_) => get_pipe(input, start, loc)
             .into_iter()
+            .map(|dir| apply(loc, dir))
             .filter(|loc| input.contains_key(loc))
             .collect(),
     }
 }
 
-#[aoc(day10, part1)]
-fn part1(input: &HashMap<Coords, Tile>) -> i32 {
-    let neighborhood = |loc| neighborhood(input, loc);
-
-    let start_loc = input
-        .iter()
-        .find_map(|(coords, tile)| (*tile == Tile::Start).then_some(*coords))
-        .unwrap();
-
-    let mut visited = HashMap::from([(start_loc, 0)]);
-    let mut recent = HashSet::from([start_loc]);
+// Directions from `loc` whose neighbour is a pipe pointing back at it.
+fn connecting_dirs(input: &HashMap<Coords, Tile>, loc: Coords) -> Vec<Dir> {
+    [Dir::N, Dir::S, Dir::E, Dir::W]
+        .into_iter()
+        .filter(|dir| match input.get(&apply(loc, *dir)) {
+            Some(Tile::Pipe(pipe)) => pipe.contains(&opposite(*dir)),
+            _ => false,
+        })
+        .collect()
+}
 
-    let mut step = 1;
-    loop {
-        let nexts = recent
-            .iter()
-            .copied()
-            .flat_map(&neighborhood)
-            .filter(|loc| !visited.contains_key(loc))
-            .collect::<HashSet<_>>();
-        if nexts.is_empty() {
-            break;
+// Follows the pipes leaving `start` towards `dir`. If they lead back to `start`, returns the
+// direction (from `start`) of the last tile before it.
+fn walk_back(input: &HashMap<Coords, Tile>, start: Coords, dir: Dir) -> Option<Dir> {
+    let mut curr = apply(start, dir);
+    let mut moving = dir;
+    for _ in 0..input.len() {
+        if curr == start {
+            return Some(opposite(moving));
         }
-        visited.extend(nexts.iter().copied().map(|loc| (loc, step)));
-        recent = nexts;
-        step += 1;
+        let Some(Tile::Pipe(pipe)) = input.get(&curr) else {
+            return None;
+        };
+        let back = opposite(moving);
+        if !pipe.contains(&back) {
+            return None;
+        }
+        moving = *pipe.iter().find(|d| **d != back).unwrap();
+        curr = apply(curr, moving);
     }
+    None
+}
 
-    visited.into_values().max().unwrap()
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StartShape {
+    pub loc: Coords,
+    pub pipe: [Dir; 2],
+    // Every neighbour pipe pointing at S, whether or not it is part of the loop.
+    pub connecting: Vec<Dir>,
 }
 
-#[aoc(day10, part2)]
-fn part2(input: &HashMap<Coords, Tile>) -> usize {
-    let neighborhood = |loc| neighborhood(input, loc);
+impl StartShape {
+    pub fn is_ambiguous(&self) -> bool {
+        self.connecting.len() > 2
+    }
+}
 
-    let start_loc = input
+// Picks the pipe under S out of the pairs of connecting neighbours, keeping only the pairs
+// whose pipes actually run from one side of S round to the other.
+pub fn infer_start(input: &HashMap<Coords, Tile>) -> Result<StartShape> {
+    let start = input
         .iter()
-        .find_map(|(coords, tile)| (*tile == Tile::Start).then_some(*coords))
-        .unwrap();
+        .filter_map(|(coords, tile)| (*tile == Tile::Start).then_some(*coords))
+        .exactly_one()
+        .map_err(|starts| anyhow!("expected exactly one S, found {}", starts.count()))?;
+    let connecting = connecting_dirs(input, start);
+    ensure!(
+        connecting.len() >= 2,
+        "S connects to {} pipe(s), not enough for a loop",
+        connecting.len()
+    );
+    let closing = connecting
+        .iter()
+        .copied()
+        .tuple_combinations()
+        .filter(|(a, b)| walk_back(input, start, *a) == Some(*b))
+        .collect_vec();
+    match closing.as_slice() {
+        [(a, b)] => Ok(StartShape {
+            loc: start,
+            pipe: [*a, *b],
+            connecting,
+        }),
+        [] => bail!("no shape for S closes a loop (connecting: {connecting:?})"),
+        _ => bail!("S is ambiguous: {closing:?} all close a loop"),
+    }
+}
+
+// Every tile on the loop, with its distance from the start.
+fn find_loop(input: &HashMap<Coords, Tile>, start: &StartShape) -> HashMap<Coords, i32> {
+    let neighborhood = |loc| neighborhood(input, start, loc);
+    let start_loc = start.loc;
 
     let mut visited = HashMap::from([(start_loc, 0)]);
     let mut recent = HashSet::from([start_loc]);
@@ -144,13 +187,36 @@ fn part2(input: &HashMap<Coords, Tile>) -> usize {
         step += 1;
     }
 
-    let pipes = visited.into_keys().collect::<HashSet<_>>();
+    visited
+}
+
+// The pipe under `loc`, with S standing for the shape already inferred for it.
+fn get_pipe(input: &HashMap<Coords, Tile>, start: &StartShape, loc: Coords) -> [Dir; 2] {
+    let tile = *input.get(&loc).unwrap();
+    match tile {
+        Tile::Pipe(pipe) => pipe,
+        Tile::Start => start.pipe,
+        _ => panic!("{tile:?}"),
+    }
+}
+
+fn bounds(input: &HashMap<Coords, Tile>) -> (Coords, Coords) {
     let MinMaxResult::MinMax(min_r, max_r) = input.keys().map(|(r, _)| *r).minmax() else {
         unreachable!()
     };
     let MinMaxResult::MinMax(min_c, max_c) = input.keys().map(|(_, c)| *c).minmax() else {
         unreachable!()
     };
+    ((min_r, min_c), (max_r, max_c))
+}
+
+// Scans each row tracking which halves (north/south) of the current cell are inside the loop.
+fn enclosed(
+    input: &HashMap<Coords, Tile>,
+    start: &StartShape,
+    pipes: &HashSet<Coords>,
+) -> HashSet<Coords> {
+    let ((min_r, min_c), (max_r, max_c)) = bounds(input);
 
     const IN_N: u8 = 1 << 0;
     const IN_S: u8 = 1 << 1;
@@ -158,24 +224,7 @@ fn part2(input: &HashMap<Coords, Tile>) -> usize {
     const OUT: u8 = 0;
 
     let mut entry_state = OUT;
-    let mut inside_count = 0usize;
-
-    let get_pipe = |loc| {
-        let tile = *input.get(&loc).unwrap();
-        match tile {
-            Tile::Pipe(pipe) => pipe,
-            Tile::Start => {
-                let nbrs = neighborhood(loc);
-                [Dir::N, Dir::S, Dir::E, Dir::W]
-                    .into_iter()
-                    .filter(|dir| nbrs.contains(&apply(loc, *dir)))
-                    .collect::<Vec<_>>()
-                    .try_into()
-                    .unwrap()
-            }
-            _ => panic!("{tile:?}"),
-        }
-    };
+    let mut inside = HashSet::new();
 
     for r in min_r - 1..=max_r + 1 {
         assert_eq!(
@@ -190,20 +239,318 @@ fn part2(input: &HashMap<Coords, Tile>) -> usize {
         );
         for c in min_c - 1..=max_c + 1 {
             if pipes.contains(&(r, c)) {
-                let pipe = get_pipe((r, c));
+                let pipe = get_pipe(input, start, (r, c));
                 if pipe.contains(&Dir::N) {
                     entry_state ^= IN_N;
                 }
                 if pipe.contains(&Dir::S) {
                     entry_state ^= IN_S;
                 }
-            } else {
-                if entry_state == IN {
-                    inside_count += 1;
-                }
+            } else if entry_state == IN {
+                inside.insert((r, c));
             }
         }
     }
 
-    inside_count
+    inside
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Orientation {
+    Clockwise,
+    CounterClockwise,
+}
+
+// The loop as a closed path of tile centres, starting at S.
+#[derive(Clone, Debug)]
+pub struct LoopGeometry {
+    pub tiles: Vec<Coords>,
+}
+
+impl LoopGeometry {
+    pub fn new(input: &HashMap<Coords, Tile>) -> Result<Self> {
+        let start = infer_start(input)?;
+        let mut tiles = vec![start.loc];
+        let mut prev = start.loc;
+        let mut curr = apply(start.loc, start.pipe[0]);
+        while curr != start.loc {
+            tiles.push(curr);
+            let next = get_pipe(input, &start, curr)
+                .into_iter()
+                .map(|dir| apply(curr, dir))
+                .find(|loc| *loc != prev)
+                .unwrap();
+            (prev, curr) = (curr, next);
+        }
+        Ok(LoopGeometry { tiles })
+    }
+
+    pub fn length(&self) -> usize {
+        self.tiles.len()
+    }
+
+    // Only the tiles where the path turns.
+    pub fn vertices(&self) -> Vec<Coords> {
+        let n = self.tiles.len();
+        (0..n)
+            .filter(|&i| {
+                let (pr, pc) = self.tiles[(i + n - 1) % n];
+                let (nr, nc) = self.tiles[(i + 1) % n];
+                pr != nr && pc != nc
+            })
+            .map(|i| self.tiles[i])
+            .collect()
+    }
+
+    // Shoelace sum over (x, y) = (column, row); with rows growing downwards a positive sum is
+    // clockwise on screen.
+    fn twice_signed_area(&self) -> i64 {
+        self.tiles
+            .iter()
+            .circular_tuple_windows()
+            .map(|((r1, c1), (r2, c2))| c1 * r2 - c2 * r1)
+            .sum()
+    }
+
+    pub fn orientation(&self) -> Orientation {
+        if self.twice_signed_area() > 0 {
+            Orientation::Clockwise
+        } else {
+            Orientation::CounterClockwise
+        }
+    }
+
+    // Area of the polygon through the tile centres.
+    pub fn area(&self) -> i64 {
+        self.twice_signed_area().abs() / 2
+    }
+
+    // Pick's theorem, A = I + B / 2 - 1, with every loop tile a boundary lattice point.
+    pub fn interior_count(&self) -> usize {
+        usize::try_from(self.area() - self.length() as i64 / 2 + 1).unwrap()
+    }
+}
+
+// Floods the outside on a doubled grid, where the loop occupies each tile's centre and the
+// midpoints between consecutive tiles; gaps between adjacent but unconnected pipes stay open,
+// so the flood squeezes through them.
+pub fn enclosed_flood_fill(input: &HashMap<Coords, Tile>) -> Result<HashSet<Coords>> {
+    let path = LoopGeometry::new(input)?.tiles;
+    let walls = path
+        .iter()
+        .circular_tuple_windows()
+        .flat_map(|((r1, c1), (r2, c2))| [(2 * r1, 2 * c1), (r1 + r2, c1 + c2)])
+        .collect::<HashSet<_>>();
+    let ((min_r, min_c), (max_r, max_c)) = bounds(input);
+    let (lo, hi) = (
+        (2 * min_r - 2, 2 * min_c - 2),
+        (2 * max_r + 2, 2 * max_c + 2),
+    );
+
+    let mut outside = HashSet::from([lo]);
+    let mut stack = vec![lo];
+    while let Some(loc) = stack.pop() {
+        for dir in [Dir::N, Dir::E, Dir::S, Dir::W] {
+            let (r, c) = apply(loc, dir);
+            if (lo.0..=hi.0).contains(&r)
+                && (lo.1..=hi.1).contains(&c)
+                && !walls.contains(&(r, c))
+                && outside.insert((r, c))
+            {
+                stack.push((r, c));
+            }
+        }
+    }
+
+    Ok(input
+        .keys()
+        .filter(|(r, c)| {
+            let doubled = (2 * r, 2 * c);
+            !walls.contains(&doubled) && !outside.contains(&doubled)
+        })
+        .copied()
+        .collect())
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Cell {
+    Start,
+    Loop([Dir; 2]),
+    Inside,
+    Junk([Dir; 2]),
+    Outside,
+}
+
+fn box_glyph(pipe: [Dir; 2]) -> char {
+    use Dir::*;
+    match pipe {
+        [N, S] | [S, N] => '│',
+        [E, W] | [W, E] => '─',
+        [N, E] | [E, N] => '└',
+        [N, W] | [W, N] => '┘',
+        [S, W] | [W, S] => '┐',
+        [S, E] | [E, S] => '┌',
+        _ => panic!("{pipe:?}"),
+    }
+}
+
+fn render_with(input: &HashMap<Coords, Tile>, draw: impl Fn(Cell) -> String) -> Result<String> {
+    let start = infer_start(input)?;
+    let pipes = find_loop(input, &start).into_keys().collect::<HashSet<_>>();
+    let inside = enclosed(input, &start, &pipes);
+    let ((min_r, min_c), (max_r, max_c)) = bounds(input);
+    Ok((min_r..=max_r)
+        .map(|r| {
+            (min_c..=max_c)
+                .map(|c| {
+                    let loc = (r, c);
+                    draw(match input.get(&loc) {
+                        Some(Tile::Start) => Cell::Start,
+                        _ if pipes.contains(&loc) => Cell::Loop(get_pipe(input, &start, loc)),
+                        _ if inside.contains(&loc) => Cell::Inside,
+                        Some(Tile::Pipe(pipe)) => Cell::Junk(*pipe),
+                        _ => Cell::Outside,
+                    })
+                })
+                .collect::<String>()
+                + "\n"
+        })
+        .collect())
+}
+
+// The loop in box-drawing characters, inside tiles shaded and pipes off the loop as dots.
+pub fn render(input: &HashMap<Coords, Tile>) -> Result<String> {
+    render_with(input, |cell| {
+        match cell {
+            Cell::Start => 'S',
+            Cell::Loop(pipe) => box_glyph(pipe),
+            Cell::Inside => '░',
+            Cell::Junk(_) => '·',
+            Cell::Outside => ' ',
+        }
+        .to_string()
+    })
+}
+
+// Like `render`, but with ANSI colours: junk pipes keep their shape and are dimmed.
+pub fn render_ansi(input: &HashMap<Coords, Tile>) -> Result<String> {
+    const RESET: &str = "\x1b[0m";
+    render_with(input, |cell| match cell {
+        Cell::Start => format!("\x1b[1;31mS{RESET}"),
+        Cell::Loop(pipe) => format!("\x1b[1;36m{}{RESET}", box_glyph(pipe)),
+        Cell::Inside => format!("\x1b[42m {RESET}"),
+        Cell::Junk(pipe) => format!("\x1b[2m{}{RESET}", box_glyph(pipe)),
+        Cell::Outside => " ".to_string(),
+    })
+}
+
+#[aoc(day10, part1)]
+fn part1(input: &HashMap<Coords, Tile>) -> Result<i32> {
+    let start = infer_start(input)?;
+    Ok(find_loop(input, &start).into_values().max().unwrap())
+}
+
+#[aoc(day10, part2)]
+fn part2(input: &HashMap<Coords, Tile>) -> Result<usize> {
+    let start = infer_start(input)?;
+    let pipes = find_loop(input, &start).into_keys().collect::<HashSet<_>>();
+    Ok(enclosed(input, &start, &pipes).len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = "FF7FSF7F7F7F7F7F---7
+L|LJ||||||||||||F--J
+FL-7LJLJ||||||LJL-77
+F--JF--7||LJLJ7F7FJ-
+L---JF-JLJ.||-FJLJJ7
+|F|F-JF---7F7-L7L|7|
+|FFJF7L7F-JF7|JL---7
+7-L-JL7||F7|L7F-7F7|
+L.L7LFJ|||||FJL7||LJ
+L7JLJL-JLJLJL--JLJ.L
+";
+
+    #[test]
+    fn sample() {
+        let input = parse(SAMPLE);
+        assert_eq!(part2(&input).unwrap(), 10);
+    }
+
+    #[test]
+    fn enclosed_area_methods_agree() {
+        let squeeze = "..........
+.S------7.
+.|F----7|.
+.||....||.
+.||....||.
+.|L-7F-J|.
+.|..||..|.
+.L--JL--J.
+..........
+";
+        for (sample, expected) in [(SAMPLE, 10), (squeeze, 4)] {
+            let input = parse(sample);
+            let start = infer_start(&input).unwrap();
+            let pipes = find_loop(&input, &start)
+                .into_keys()
+                .collect::<HashSet<_>>();
+            let geometry = LoopGeometry::new(&input).unwrap();
+            assert_eq!(geometry.length(), pipes.len());
+            assert_eq!(enclosed(&input, &start, &pipes).len(), expected);
+            assert_eq!(enclosed_flood_fill(&input).unwrap().len(), expected);
+            assert_eq!(geometry.interior_count(), expected);
+        }
+    }
+
+    #[test]
+    fn start_inference() {
+        // S touches three pipes; only N+E closes a loop.
+        let input = parse(".....\n..F7.\n.-SJ.\n..|..\n.....\n");
+        let shape = infer_start(&input).unwrap();
+        assert_eq!(shape.loc, (3, 3));
+        assert_eq!(shape.pipe, [Dir::N, Dir::E]);
+        assert!(shape.is_ambiguous());
+
+        assert!(infer_start(&parse("...\n.S-\n...\n")).is_err());
+        assert!(infer_start(&parse(".|.\n-S-\n...\n")).is_err());
+        assert!(part1(&parse(".|.\n-S.\n...\n")).is_err());
+    }
+
+    #[test]
+    fn bad_starts_are_errors() {
+        for bad in [".|.\n-S-\n...\n", "...\n.S-\n...\n"] {
+            let input = parse(bad);
+            assert!(LoopGeometry::new(&input).is_err());
+            assert!(enclosed_flood_fill(&input).is_err());
+            assert!(render(&input).is_err());
+            assert!(render_ansi(&input).is_err());
+        }
+    }
+
+    #[test]
+    fn loop_geometry() {
+        let geometry = LoopGeometry::new(&parse(".....\n.S-7.\n.|.|.\n.L-J.\n.....\n")).unwrap();
+        assert_eq!(geometry.length(), 8);
+        assert_eq!(geometry.vertices(), [(2, 2), (4, 2), (4, 4), (2, 4)]);
+        assert_eq!(geometry.orientation(), Orientation::CounterClockwise);
+        assert_eq!(geometry.area(), 4);
+    }
+
+    #[test]
+    fn rendering() {
+        let input = parse(".....\n.S-7.\n.|.|.\n.L-J.\n.....\n");
+        assert_eq!(
+            render(&input).unwrap(),
+            "     \n S─┐ \n │░│ \n └─┘ \n     \n"
+        );
+        let input = parse("-L|F7\n7S-7|\nL|7||\n-L-J|\nL|-JF\n");
+        assert_eq!(
+            render(&input).unwrap(),
+            "·····\n·S─┐·\n·│░│·\n·└─┘·\n·····\n"
+        );
+        assert!(render_ansi(&input).unwrap().contains("\x1b[2m┘"));
+    }
 }
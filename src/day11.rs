@@ -1,4 +1,4 @@
-use std::collections::{BTreeSet, HashSet};
+use std::{cmp::Reverse, collections::BTreeSet};
 
 use aoc_runner_derive::{aoc, aoc_generator};
 use itertools::Itertools;
@@ -18,71 +18,186 @@ fn parse(input: &str) -> Vec<Vec<bool>> {
         .collect::<Vec<_>>()
 }
 
-#[aoc(day11, part1)]
-fn part1(input: &[Vec<bool>]) -> usize {
-    let mut input = input.to_vec();
-    for i in (0..input.len()).rev() {
-        if input[i].iter().all(|t| !t) {
-            let all_bools = input[i].clone();
-            input.insert(i, all_bools);
-        }
-    }
+fn empty_lines(input: &[Vec<bool>]) -> (BTreeSet<usize>, BTreeSet<usize>) {
+    let big_rows = (0..input.len())
+        .filter(|&i| input[i].iter().all(|t| !t))
+        .collect();
+    let big_cols = (0..input.first().map_or(0, Vec::len))
+        .filter(|&i| input.iter().all(|line| !line[i]))
+        .collect();
+    (big_rows, big_cols)
+}
 
-    for i in (0..input[0].len()).rev() {
-        if input.iter().all(|line| !line[i]) {
-            for line in input.iter_mut() {
-                line.insert(i, false);
-            }
-        }
+// Where each index lands once every big line before it is `expansion` lines wide.
+fn expanded_axis(len: usize, big: &BTreeSet<usize>, expansion: usize) -> Vec<usize> {
+    let mut expanded = Vec::with_capacity(len);
+    let mut pos = 0;
+    for i in 0..len {
+        expanded.push(pos);
+        pos += if big.contains(&i) { expansion } else { 1 };
     }
+    expanded
+}
 
-    let mapped = input
-        .into_iter()
-        .map(|line| line.into_iter().enumerate())
+fn galaxies(input: &[Vec<bool>]) -> impl Iterator<Item = (usize, usize)> + '_ {
+    input
+        .iter()
+        .map(|line| line.iter().enumerate())
         .enumerate()
         .flat_map(|(r, line)| line.flat_map(move |(c, t)| t.then_some((r, c))))
-        .collect::<HashSet<_>>();
+}
 
-    mapped
-        .iter()
-        .tuple_combinations::<(_, _)>()
-        .map(|((a, b), (c, d))| c.abs_diff(*a) + d.abs_diff(*b))
-        .sum::<usize>()
+// Sum of |x_i - x_j| over all pairs: once sorted, the k-th smallest value is subtracted by the
+// k values below it and added by the n - 1 - k above it.
+fn pairwise_abs_sum(mut xs: Vec<usize>) -> u128 {
+    xs.sort_unstable();
+    let mut below = 0u128;
+    let mut sum = 0u128;
+    for (k, x) in xs.into_iter().enumerate() {
+        let x = x as u128;
+        sum += x * k as u128 - below;
+        below += x;
+    }
+    sum
 }
 
-#[aoc(day11, part2)]
-fn part2(input: &[Vec<bool>]) -> usize {
-    let mut big_rows: BTreeSet<usize> = BTreeSet::new();
-    for i in (0..input.len()).rev() {
-        if input[i].iter().all(|t| !t) {
-            big_rows.insert(i);
+#[derive(Clone, Debug)]
+pub struct Universe {
+    // Expanded (row, column) of each galaxy, in reading order of the original grid.
+    galaxies: Vec<(usize, usize)>,
+}
+
+impl Universe {
+    pub fn new(input: &[Vec<bool>], expansion: usize) -> Self {
+        let (big_rows, big_cols) = empty_lines(input);
+        let rows = expanded_axis(input.len(), &big_rows, expansion);
+        let cols = expanded_axis(input.first().map_or(0, Vec::len), &big_cols, expansion);
+        Universe {
+            galaxies: galaxies(input).map(|(r, c)| (rows[r], cols[c])).collect(),
         }
     }
 
-    let mut big_cols: BTreeSet<usize> = BTreeSet::new();
-    for i in (0..input[0].len()).rev() {
-        if input.iter().all(|line| !line[i]) {
-            big_cols.insert(i);
+    pub fn galaxies(&self) -> &[(usize, usize)] {
+        &self.galaxies
+    }
+
+    pub fn distance(&self, a: usize, b: usize) -> usize {
+        let ((r1, c1), (r2, c2)) = (self.galaxies[a], self.galaxies[b]);
+        r1.abs_diff(r2) + c1.abs_diff(c2)
+    }
+
+    pub fn distance_matrix(&self) -> Vec<Vec<usize>> {
+        (0..self.galaxies.len())
+            .map(|a| {
+                (0..self.galaxies.len())
+                    .map(|b| self.distance(a, b))
+                    .collect()
+            })
+            .collect()
+    }
+
+    // The k galaxies closest to `galaxy` as (index, distance), nearest first.
+    pub fn nearest(&self, galaxy: usize, k: usize) -> Vec<(usize, usize)> {
+        (0..self.galaxies.len())
+            .filter(|other| *other != galaxy)
+            .map(|other| (self.distance(galaxy, other), other))
+            .k_smallest(k)
+            .map(|(d, other)| (other, d))
+            .collect()
+    }
+
+    // Manhattan distance is the larger of the spreads along r + c and r - c, so the farthest
+    // pair is found from the extremes of those two projections in linear time. None if there are
+    // fewer than two galaxies.
+    pub fn farthest_pair(&self) -> Option<(usize, usize, usize)> {
+        if self.galaxies.len() < 2 {
+            return None;
         }
+        [1i64, -1]
+            .into_iter()
+            .filter_map(|sign| {
+                let (lo, hi) = (0..self.galaxies.len())
+                    .minmax_by_key(|&i| {
+                        let (r, c) = self.galaxies[i];
+                        r as i64 + sign * c as i64
+                    })
+                    .into_option()?;
+                Some((lo.min(hi), lo.max(hi), self.distance(lo, hi)))
+            })
+            .max_by_key(|(a, b, d)| (*d, Reverse((*a, *b))))
     }
 
-    let mapped = input
-        .into_iter()
-        .map(|line| line.into_iter().enumerate())
-        .enumerate()
-        .flat_map(|(r, line)| line.flat_map(move |(c, t)| t.then_some((r, c))))
-        .collect::<HashSet<_>>();
+    // Sum over all pairs in O(n log n): the two axes contribute independently.
+    pub fn total_distance(&self) -> u128 {
+        let (rs, cs) = self.galaxies.iter().copied().unzip();
+        pairwise_abs_sum(rs) + pairwise_abs_sum(cs)
+    }
+}
 
-    mapped
-        .iter()
-        .tuple_combinations::<(_, _)>()
-        .map(|((a, b), (c, d))| {
-            let num_big_rows = big_rows.range(a.min(c)..a.max(c)).count();
-            let num_big_cols = big_cols.range(b.min(d)..b.max(d)).count();
-            c.abs_diff(*a) + num_big_rows * 1000000 - num_big_rows
-                + d.abs_diff(*b)
-                + num_big_cols * 1000000
-                - num_big_cols
-        })
-        .sum::<usize>()
+// All-pairs Manhattan distance with each empty row and column `expansion` times as wide.
+pub fn galaxy_distances(input: &[Vec<bool>], expansion: usize) -> u128 {
+    Universe::new(input, expansion).total_distance()
+}
+
+#[aoc(day11, part1)]
+fn part1(input: &[Vec<bool>]) -> u128 {
+    galaxy_distances(input, 2)
+}
+
+#[aoc(day11, part2)]
+fn part2(input: &[Vec<bool>]) -> u128 {
+    galaxy_distances(input, 1000000)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = "...#......
+.......#..
+#.........
+..........
+......#...
+.#........
+.........#
+..........
+.......#..
+#...#.....
+";
+
+    #[test]
+    fn sample() {
+        let input = parse(SAMPLE);
+        assert_eq!(part1(&input), 374);
+        assert_eq!(galaxy_distances(&input, 10), 1030);
+        assert_eq!(galaxy_distances(&input, 100), 8410);
+    }
+
+    #[test]
+    fn universe_queries() {
+        let universe = Universe::new(&parse(SAMPLE), 2);
+        // Galaxies 5 and 9 in the puzzle's 1-based numbering.
+        assert_eq!(universe.distance(4, 8), 9);
+        let matrix = universe.distance_matrix();
+        let total = matrix.iter().flatten().sum::<usize>() / 2;
+        assert_eq!(total as u128, universe.total_distance());
+
+        let mut by_distance = (1..9).map(|o| (o, matrix[0][o])).collect::<Vec<_>>();
+        by_distance.sort_by_key(|(o, d)| (*d, *o));
+        assert_eq!(universe.nearest(0, 3), by_distance[..3]);
+
+        let (a, b, d) = universe.farthest_pair().unwrap();
+        assert_eq!(d, *matrix.iter().flatten().max().unwrap());
+        assert_eq!(matrix[a][b], d);
+    }
+
+    #[test]
+    fn farthest_pair_needs_two_galaxies() {
+        assert_eq!(Universe::new(&parse("...\n.#.\n"), 2).farthest_pair(), None);
+        assert_eq!(Universe::new(&parse("...\n"), 2).farthest_pair(), None);
+        assert_eq!(
+            Universe::new(&parse("#..\n..#\n"), 2).farthest_pair(),
+            Some((0, 1, 4))
+        );
+    }
 }
@@ -1,25 +1,20 @@
-use std::collections::HashMap;
+use std::ops::AddAssign;
 
 use aoc_runner_derive::{aoc, aoc_generator};
-use itertools::{put_back_n, Itertools};
+use itertools::Itertools;
+use num::{BigInt, BigRational, BigUint, One, ToPrimitive, Zero};
 
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
-enum Spring {
+pub enum Spring {
     Fine,
     Damaged,
 }
 
+// `None` is an unknown spring.
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
-struct InputLine {
-    row: Vec<Option<Spring>>,
-    sizes: Vec<usize>,
-}
-
-#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
-struct Line {
-    runs: Vec<Run>,
-    sizes: Vec<usize>,
-    force_next_damaged: bool,
+pub struct Line {
+    pub row: Vec<Option<Spring>>,
+    pub sizes: Vec<usize>,
 }
 
 #[aoc_generator(day12)]
@@ -27,257 +22,556 @@ fn parse(input: &str) -> Vec<Line> {
     input
         .lines()
         .map(|line| {
-            let (row, sizes) = line.split_once(" ").unwrap();
-            let row = row.chars().map(|c| match c {
-                '.' => Some(Spring::Fine),
-                '#' => Some(Spring::Damaged),
-                '?' => None,
-                _ => panic!("{c}"),
-            });
-            let runs = row
-                .group_by(|spring| *spring)
-                .into_iter()
-                .map(|(spring, springs)| {
-                    let len = springs.count();
-                    match spring {
-                        Some(Spring::Damaged) => Run::Damaged(len),
-                        Some(Spring::Fine) => Run::Fine(len),
-                        None => Run::Unknown(len),
-                    }
+            let (row, sizes) = line.split_once(' ').unwrap();
+            let row = row
+                .chars()
+                .map(|c| match c {
+                    '.' => Some(Spring::Fine),
+                    '#' => Some(Spring::Damaged),
+                    '?' => None,
+                    _ => panic!("{c}"),
                 })
                 .collect_vec();
             let sizes = sizes
-                .split(",")
+                .split(',')
                 .map(|n| n.parse::<usize>().unwrap())
                 .collect_vec();
-            Line {
-                runs,
-                sizes,
-                force_next_damaged: false,
-            }
+            Line { row, sizes }
         })
         .collect_vec()
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
-enum Run {
-    Fine(usize),
-    Damaged(usize),
-    Unknown(usize),
-}
+impl Line {
+    // Counts arrangements group by group, keeping two rows of the table at a time. For the first
+    // g groups and a prefix row[..i]:
+    //   open[i]  = ways with cell i - 1 fine (or i = 0), i.e. ready for another group;
+    //   ended[i] = ways with group g ending exactly at cell i - 1.
+    // Each row of the table depends only on the previous group's `open` row, so the whole count
+    // is O(len × groups) time and O(len) space.
+    pub fn count_arrangements<T>(&self) -> T
+    where
+        T: Clone + Zero + One + for<'a> AddAssign<&'a T>,
+    {
+        let n = self.row.len();
+        let fine_before = std::iter::once(0)
+            .chain(self.row.iter().scan(0, |count, spring| {
+                *count += usize::from(*spring == Some(Spring::Fine));
+                Some(*count)
+            }))
+            .collect_vec();
+        let can_be = |i: usize, spring: Spring| self.row[i].is_none_or(|s| s == spring);
 
-fn num_arrangements_inner(table: &mut HashMap<Line, usize>, line: Line) -> usize {
-    let Line {
-        runs,
-        sizes,
-        force_next_damaged,
-    } = line;
-
-    let mut runs = put_back_n(runs.iter().copied());
-    let mut sizes = put_back_n(sizes.iter().copied());
-
-    let size = sizes.next();
-    let run = runs.next();
-
-    fn consume_one_fine_and_recurse(
-        table: &mut HashMap<Line, usize>,
-        sizes: itertools::PutBackN<std::iter::Copied<std::slice::Iter<'_, usize>>>,
-        mut runs: itertools::PutBackN<std::iter::Copied<std::slice::Iter<'_, Run>>>,
-    ) -> usize {
-        // Now we need at least one "fine" in order to recurse, or to have reached the end.
-        match runs.next() {
-            None => {
-                if sizes.count() == 0 {
-                    1
+        let mut open = vec![T::zero(); n + 1];
+        let mut ended = vec![T::zero(); n + 1];
+        open[0] = T::one();
+        for i in 1..=n {
+            if can_be(i - 1, Spring::Fine) {
+                open[i] = open[i - 1].clone();
+            }
+        }
+
+        for &size in &self.sizes {
+            let prev_open = std::mem::replace(&mut open, vec![T::zero(); n + 1]);
+            for j in 0..=n {
+                ended[j] = if j >= size && fine_before[j] == fine_before[j - size] {
+                    prev_open[j - size].clone()
                 } else {
-                    0
+                    T::zero()
+                };
+                if j > 0 && can_be(j - 1, Spring::Fine) {
+                    let mut ways = open[j - 1].clone();
+                    ways += &ended[j - 1];
+                    open[j] = ways;
                 }
             }
-            Some(run) => {
-                match run {
-                    Run::Damaged(_) => 0,
-                    Run::Fine(next_fine) => {
-                        if next_fine == 1 {
-                            // Ok, we consume this and recurse.
-                            num_arrangements(
-                                table,
-                                Line {
-                                    sizes: sizes.collect_vec(),
-                                    runs: runs.collect_vec(),
-                                    force_next_damaged: false,
-                                },
-                            )
-                        } else {
-                            // Take away one (must still be nonzero), then put back and recurse.
-                            runs.put_back(Run::Fine(next_fine.checked_sub(1).unwrap()));
-                            num_arrangements(
-                                table,
-                                Line {
-                                    sizes: sizes.collect_vec(),
-                                    runs: runs.collect_vec(),
-                                    force_next_damaged: false,
-                                },
-                            )
-                        }
-                    }
-                    Run::Unknown(next_unknown) => {
-                        if next_unknown == 1 {
-                            // Ok, we consume this and recurse.
-                            num_arrangements(
-                                table,
-                                Line {
-                                    sizes: sizes.collect_vec(),
-                                    runs: runs.collect_vec(),
-                                    force_next_damaged: false,
-                                },
-                            )
-                        } else {
-                            // Take away one (must still be nonzero), then put back and recurse.
-                            runs.put_back(Run::Unknown(next_unknown.checked_sub(1).unwrap()));
-                            num_arrangements(
-                                table,
-                                Line {
-                                    sizes: sizes.collect_vec(),
-                                    runs: runs.collect_vec(),
-                                    force_next_damaged: false,
-                                },
-                            )
-                        }
-                    }
+        }
+
+        let mut total = open[n].clone();
+        total += &ended[n];
+        total
+    }
+
+    pub fn arrangements(&self) -> u128 {
+        self.count_arrangements()
+    }
+
+    pub fn arrangements_big(&self) -> BigUint {
+        self.count_arrangements()
+    }
+}
+
+// ways[g][i]: arrangements of row[i..] holding exactly groups g.., when cell i is free to start
+// a group (i = 0, or cell i - 1 is fine); that is, the count for row[i..] and sizes[g..] on their
+// own. Stored whole, in big integers like `arrangements_big`, so that enumeration, sampling and
+// the damage counts can steer by it on any line.
+fn suffix_table(line: &Line) -> Vec<Vec<BigUint>> {
+    let Line { row, sizes } = line;
+    let n = row.len();
+    let mut ways = vec![vec![BigUint::zero(); n + 1]; sizes.len() + 1];
+    ways[sizes.len()][n] = BigUint::one();
+    for g in (0..=sizes.len()).rev() {
+        for i in (0..n).rev() {
+            let mut total = BigUint::zero();
+            if row[i] != Some(Spring::Damaged) {
+                total += &ways[g][i + 1];
+            }
+            if g < sizes.len() {
+                if let Some(next) = group_fits(line, i, sizes[g]) {
+                    total += &ways[g + 1][next];
                 }
             }
+            ways[g][i] = total;
         }
     }
+    ways
+}
 
-    match (size, run) {
-        (None, None) => 1,
-        (None, Some(run)) => {
-            runs.put_back(run);
-            if runs.any(|run| matches!(run, Run::Damaged(_))) {
-                0
-            } else {
-                1
+// If a group of `size` can start at `start`, where the next group could start after it.
+fn group_fits(line: &Line, start: usize, size: usize) -> Option<usize> {
+    let end = start + size;
+    let fits = end <= line.row.len()
+        && line.row[start..end]
+            .iter()
+            .all(|s| *s != Some(Spring::Fine))
+        && line
+            .row
+            .get(end)
+            .is_none_or(|s| *s != Some(Spring::Damaged));
+    fits.then_some((end + 1).min(line.row.len()))
+}
+
+// a / b as a float, scaling both down first so that huge counts don't overflow to infinity.
+fn ratio(a: &BigUint, b: &BigUint) -> f64 {
+    let shift = b.bits().saturating_sub(1000);
+    let (a, b) = (a >> shift, b >> shift);
+    a.to_f64().unwrap() / b.to_f64().unwrap()
+}
+
+// SplitMix64, so that samples are reproducible from a seed.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    // Uniform in 0..bound, by rejection from the numbers with as many bits as bound.
+    fn below(&mut self, bound: &BigUint) -> BigUint {
+        let bits = bound.bits();
+        let digits = bits.div_ceil(32);
+        loop {
+            let x = BigUint::new((0..digits).map(|_| (self.next() >> 32) as u32).collect())
+                >> (digits * 32 - bits);
+            if &x < bound {
+                return x;
             }
         }
-        (Some(_size), None) => 0,
-        (Some(size), Some(run)) => match run {
-            Run::Fine(_) => {
-                if force_next_damaged {
-                    0
-                } else {
-                    sizes.put_back(size);
-                    num_arrangements(
-                        table,
-                        Line {
-                            runs: runs.collect_vec(),
-                            sizes: sizes.collect_vec(),
-                            force_next_damaged: false,
-                        },
-                    )
+    }
+}
+
+pub fn render(arrangement: &[Spring]) -> String {
+    arrangement
+        .iter()
+        .map(|s| match s {
+            Spring::Fine => '.',
+            Spring::Damaged => '#',
+        })
+        .collect()
+}
+
+impl Line {
+    pub fn reversed(&self) -> Line {
+        Line {
+            row: self.row.iter().rev().copied().collect(),
+            sizes: self.sizes.iter().rev().copied().collect(),
+        }
+    }
+
+    // Every valid arrangement, lazily and in lexicographic order with fine before damaged. Dead
+    // branches are cut using the suffix counts, so each step of the iterator does bounded work.
+    pub fn enumerate(&self) -> impl Iterator<Item = Vec<Spring>> + '_ {
+        let ways = suffix_table(self);
+        let mut stack = vec![(0usize, 0usize, Vec::new())];
+        std::iter::from_fn(move || {
+            while let Some((g, i, prefix)) = stack.pop() {
+                if ways[g][i.min(self.row.len())].is_zero() {
+                    continue;
                 }
-            }
-            Run::Damaged(this_damaged) => match size.cmp(&this_damaged) {
-                std::cmp::Ordering::Less => 0,
-                std::cmp::Ordering::Equal => consume_one_fine_and_recurse(table, sizes, runs),
-                std::cmp::Ordering::Greater => {
-                    sizes.put_back(size.checked_sub(this_damaged).unwrap());
-                    num_arrangements(
-                        table,
-                        Line {
-                            runs: runs.collect_vec(),
-                            sizes: sizes.collect_vec(),
-                            force_next_damaged: true,
-                        },
-                    )
+                if i >= self.row.len() {
+                    return Some(prefix);
+                }
+                if g < self.sizes.len() {
+                    if let Some(next) = group_fits(self, i, self.sizes[g]) {
+                        let mut placed = prefix.clone();
+                        placed.extend(std::iter::repeat_n(Spring::Damaged, self.sizes[g]));
+                        placed.resize(next, Spring::Fine);
+                        stack.push((g + 1, next, placed));
+                    }
                 }
-            },
-            Run::Unknown(this_unknown) => {
-                // Recurse treating this as 1 damaged or 1 not damaged.
-                let after_1 = this_unknown - 1;
-                sizes.put_back(size);
-                let sizes = sizes.collect_vec();
-                if after_1 > 0 {
-                    runs.put_back(Run::Unknown(after_1));
+                if self.row[i] != Some(Spring::Damaged) {
+                    let mut fine = prefix;
+                    fine.push(Spring::Fine);
+                    stack.push((g, i + 1, fine));
                 }
+            }
+            None
+        })
+    }
 
-                let runs = runs.collect_vec();
+    // A uniformly random arrangement, or None if there are none.
+    pub fn sample(&self, seed: u64) -> Option<Vec<Spring>> {
+        let ways = suffix_table(self);
+        let mut rng = Rng(seed);
+        let (mut g, mut i) = (0, 0);
+        let mut arrangement = Vec::with_capacity(self.row.len());
+        if ways[0][0].is_zero() {
+            return None;
+        }
+        while i < self.row.len() {
+            let fine_ways = if self.row[i] != Some(Spring::Damaged) {
+                ways[g][i + 1].clone()
+            } else {
+                BigUint::zero()
+            };
+            if rng.below(&ways[g][i]) < fine_ways {
+                arrangement.push(Spring::Fine);
+                i += 1;
+            } else {
+                let next = group_fits(self, i, self.sizes[g]).unwrap();
+                arrangement.extend(std::iter::repeat_n(Spring::Damaged, self.sizes[g]));
+                arrangement.resize(next, Spring::Fine);
+                (g, i) = (g + 1, next);
+            }
+        }
+        Some(arrangement)
+    }
 
-                // as 1 damaged
-                let mut runs_with_one_damaged = put_back_n(runs.iter().copied());
-                runs_with_one_damaged.put_back(Run::Damaged(1));
+    // For each cell, the number of arrangements in which it is damaged, alongside the total:
+    // every placement of group g at a start contributes (ways before) * (ways after) to the cells
+    // it covers. The ways before come from the same table built over the reversed line.
+    pub fn damage_counts(&self) -> (Vec<BigUint>, BigUint) {
+        let n = self.row.len();
+        let groups = self.sizes.len();
+        let (reversed, after) = (suffix_table(&self.reversed()), suffix_table(self));
+        // Arrangements of row[..i] holding exactly groups ..g with cell i free to start a group.
+        let before = |g: usize, i: usize| match i {
+            0 if g == 0 => BigUint::one(),
+            0 => BigUint::zero(),
+            i if self.row[i - 1] != Some(Spring::Damaged) => {
+                reversed[groups - g][n - i + 1].clone()
+            }
+            _ => BigUint::zero(),
+        };
+        // Placements covering cells start..end are added at start and taken away at end, so the
+        // running difference is always the count for the current cell.
+        let mut starts = vec![BigUint::zero(); n];
+        let mut ends = vec![BigUint::zero(); n + 1];
+        for (g, &size) in self.sizes.iter().enumerate() {
+            for i in 0..n {
+                if let Some(next) = group_fits(self, i, size) {
+                    let ways = before(g, i) * &after[g + 1][next];
+                    starts[i] += &ways;
+                    ends[i + size] += ways;
+                }
+            }
+        }
+        let (mut started, mut ended) = (BigUint::zero(), BigUint::zero());
+        let damaged = starts
+            .iter()
+            .zip(&ends)
+            .map(|(start, end)| {
+                started += start;
+                ended += end;
+                &started - &ended
+            })
+            .collect();
+        (damaged, after[0][0].clone())
+    }
 
-                let ways_with_one_damaged = num_arrangements(
-                    table,
-                    Line {
-                        runs: runs_with_one_damaged.collect_vec(),
-                        sizes: sizes.clone(),
-                        force_next_damaged,
-                    },
-                );
+    // For each cell, the fraction of arrangements in which it is damaged.
+    pub fn damage_probabilities(&self) -> Vec<f64> {
+        let (damaged, total) = self.damage_counts();
+        damaged
+            .iter()
+            .map(|d| {
+                if total.is_zero() {
+                    0.0
+                } else {
+                    ratio(d, &total)
+                }
+            })
+            .collect()
+    }
 
-                // as 1 fine
-                let mut runs_with_one_fine = put_back_n(runs.iter().copied());
-                runs_with_one_fine.put_back(Run::Fine(1));
-
-                let ways_with_one_fine = num_arrangements(
-                    table,
-                    Line {
-                        runs: runs_with_one_fine.collect_vec(),
-                        sizes: sizes.clone(),
-                        force_next_damaged,
-                    },
-                );
+    // The cells that take the same value in every arrangement, or None if there are no
+    // arrangements at all.
+    pub fn forced(&self) -> Option<Vec<Option<Spring>>> {
+        let (damaged, total) = self.damage_counts();
+        (!total.is_zero()).then(|| {
+            damaged
+                .into_iter()
+                .map(|d| {
+                    if d.is_zero() {
+                        Some(Spring::Fine)
+                    } else if d == total {
+                        Some(Spring::Damaged)
+                    } else {
+                        None
+                    }
+                })
+                .collect()
+        })
+    }
+}
 
-                ways_with_one_damaged + ways_with_one_fine
+impl Line {
+    // `factor` copies of the line, with `separator` between consecutive copies of the row.
+    pub fn unfold(&self, factor: usize, separator: &[Option<Spring>]) -> Line {
+        let row =
+            Itertools::intersperse(std::iter::repeat_n(self.row.as_slice(), factor), separator)
+                .flatten()
+                .copied()
+                .collect();
+        Line {
+            row,
+            sizes: self.sizes.repeat(factor),
+        }
+    }
+
+    // Arrangements of `unfold(factor, separator)` without building the full line when it can be
+    // avoided. The count for k copies often satisfies a short linear recurrence; Berlekamp–Massey
+    // finds the shortest one from the counts for small k, and once it has held for
+    // `CONFIRMATIONS` further terms it is used to run up to `factor`. Plenty of lines have no
+    // such recurrence (`?? 1` gives C(2k, k)), so the search stops after `MAX_TERMS` counts and
+    // the full line is counted directly instead.
+    pub fn unfolded_arrangements(&self, factor: usize, separator: &[Option<Spring>]) -> BigUint {
+        const CONFIRMATIONS: usize = 8;
+        const MAX_TERMS: usize = 24;
+        let mut counts = Vec::new();
+        for k in 1..=factor.min(MAX_TERMS) {
+            let count = self.unfold(k, separator).arrangements_big();
+            if k == factor {
+                return count;
+            }
+            counts.push(BigInt::from(count));
+            let recurrence = berlekamp_massey(&counts);
+            if counts.len() >= 2 * recurrence.len() + CONFIRMATIONS {
+                if let Some(count) = extend(&counts, &recurrence, factor) {
+                    return count;
+                }
             }
-        },
+        }
+        self.unfold(factor, separator).arrangements_big()
+    }
+}
+
+// Coefficients c of the shortest recurrence s[n] = c[0] s[n - 1] + c[1] s[n - 2] + ... that
+// generates all of `seq`, over the rationals.
+fn berlekamp_massey(seq: &[BigInt]) -> Vec<BigRational> {
+    let mut current = vec![BigRational::one()];
+    let mut previous = vec![BigRational::one()];
+    let mut len = 0;
+    let mut shift = 1;
+    let mut last_discrepancy = BigRational::one();
+    for n in 0..seq.len() {
+        let discrepancy = (0..=len).fold(BigRational::zero(), |acc, i| {
+            acc + current.get(i).cloned().unwrap_or_default()
+                * BigRational::from(seq[n - i].clone())
+        });
+        if discrepancy.is_zero() {
+            shift += 1;
+            continue;
+        }
+        let scale = &discrepancy / &last_discrepancy;
+        let mut next = current.clone();
+        next.resize(next.len().max(previous.len() + shift), BigRational::zero());
+        for (i, b) in previous.iter().enumerate() {
+            next[i + shift] -= &scale * b;
+        }
+        if 2 * len <= n {
+            previous = std::mem::replace(&mut current, next);
+            len = n + 1 - len;
+            last_discrepancy = discrepancy;
+            shift = 1;
+        } else {
+            current = next;
+            shift += 1;
+        }
     }
+    current.resize(len + 1, BigRational::zero());
+    current[1..].iter().map(|c| -c).collect()
 }
 
-fn num_arrangements(table: &mut HashMap<Line, usize>, line: Line) -> usize {
-    if let Some(n) = table.get(&line) {
-        return *n;
+// Runs `recurrence` on from `seq` (the counts for 1, 2, ... copies) to the count for `factor`
+// copies, or None if it ever produces a non-integer or negative count.
+fn extend(seq: &[BigInt], recurrence: &[BigRational], factor: usize) -> Option<BigUint> {
+    let mut seq = seq.to_vec();
+    while seq.len() < factor {
+        let next = recurrence
+            .iter()
+            .zip(seq.iter().rev())
+            .fold(BigRational::zero(), |acc, (c, s)| {
+                acc + c * BigRational::from(s.clone())
+            });
+        if !next.is_integer() {
+            return None;
+        }
+        seq.push(next.to_integer());
     }
-    let n = num_arrangements_inner(table, line.clone());
-    table.insert(line.clone(), n);
-    n
+    seq[factor - 1].to_biguint()
 }
 
 #[aoc(day12, part1)]
-fn part1(input: &[Line]) -> usize {
-    let mut table = HashMap::new();
-    input
-        .iter()
-        .map(|line| num_arrangements(&mut table, line.clone()))
-        .sum()
+fn part1(input: &[Line]) -> u128 {
+    input.iter().map(Line::arrangements).sum()
 }
 
 #[aoc(day12, part2)]
-fn part2(input: &[Line]) -> usize {
-    let mut table = HashMap::new();
+fn part2(input: &[Line]) -> u128 {
     input
         .iter()
-        .map(|line| {
-            let Line {
-                runs,
-                sizes,
-                force_next_damaged: _,
-            } = line.clone();
-            let runs = Itertools::intersperse(
-                (1..=5).map(|_| runs.clone()).into_iter(),
-                vec![Run::Unknown(1)],
-            )
-            .flatten()
-            .collect_vec();
-            let sizes = (1..=5).map(|_| sizes.clone()).flatten().collect_vec();
-            num_arrangements(
-                &mut table,
-                Line {
-                    runs,
-                    sizes,
-                    force_next_damaged: false,
-                },
-            )
-        })
+        .map(|line| line.unfold(5, &[None]).arrangements())
         .sum()
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    const SAMPLE: &str = "???.### 1,1,3
+.??..??...?##. 1,1,3
+?#?#?#?#?#?#?#? 1,3,1,6
+????.#...#... 4,1,1
+????.######..#####. 1,6,5
+?###???????? 3,2,1
+";
+
+    #[test]
+    fn sample() {
+        let input = parse(SAMPLE);
+        assert_eq!(
+            input.iter().map(Line::arrangements).collect_vec(),
+            [1, 4, 1, 1, 4, 10]
+        );
+        assert_eq!(part1(&input), 21);
+        assert_eq!(part2(&input), 525152);
+    }
+
+    #[test]
+    fn enumeration_and_sampling() {
+        for line in parse(SAMPLE) {
+            let all = line.enumerate().collect_vec();
+            assert_eq!(all.len() as u128, line.arrangements());
+            assert!(all.iter().all_unique());
+            for arrangement in &all {
+                let fixed = Line {
+                    row: arrangement.iter().copied().map(Some).collect(),
+                    sizes: line.sizes.clone(),
+                };
+                assert_eq!(fixed.arrangements(), 1, "{}", render(arrangement));
+                assert!(line
+                    .row
+                    .iter()
+                    .zip(arrangement)
+                    .all(|(known, s)| known.is_none_or(|k| k == *s)));
+            }
+            let samples = (0..200)
+                .map(|seed| line.sample(seed).unwrap())
+                .collect::<HashSet<_>>();
+            assert_eq!(samples, all.into_iter().collect());
+        }
+    }
+
+    #[test]
+    fn probabilities() {
+        let line = &parse("???.### 1,1,3")[0];
+        assert_eq!(
+            line.damage_probabilities(),
+            [1.0, 0.0, 1.0, 0.0, 1.0, 1.0, 1.0]
+        );
+        let line = &parse("?###???????? 3,2,1")[0];
+        let probabilities = line.damage_probabilities();
+        let all = line.enumerate().collect_vec();
+        for (cell, p) in probabilities.into_iter().enumerate() {
+            let damaged = all.iter().filter(|a| a[cell] == Spring::Damaged).count();
+            assert_eq!(p, damaged as f64 / all.len() as f64);
+        }
+    }
+
+    #[test]
+    fn unfolding() {
+        let input = parse(SAMPLE);
+        for line in &input {
+            for factor in 0..=12 {
+                assert_eq!(
+                    line.unfolded_arrangements(factor, &[None]),
+                    line.unfold(factor, &[None]).arrangements_big(),
+                    "{line:?} x{factor}"
+                );
+            }
+            let separator = [Some(Spring::Fine), None];
+            assert_eq!(
+                line.unfolded_arrangements(30, &separator),
+                line.unfold(30, &separator).arrangements_big()
+            );
+        }
+        // 506250 at 5 copies, growing by a factor of 15 per copy.
+        let last = &input[5];
+        assert_eq!(
+            last.unfolded_arrangements(1000, &[None]),
+            BigUint::from(10u32) * BigUint::from(15u32).pow(999)
+        );
+    }
+
+    #[test]
+    fn unfolding_without_a_recurrence() {
+        let line = &parse("?? 1")[0];
+        let k = 1000u32;
+        // k groups of one in 3k - 1 cells leave 2k gaps to choose from.
+        let expected = (0..k).fold(BigUint::one(), |acc, i| acc * (2 * k - i) / (i + 1));
+        assert_eq!(line.unfolded_arrangements(k as usize, &[None]), expected);
+    }
+
+    #[test]
+    fn long_rows_in_big_integers() {
+        let line = Line {
+            row: vec![None; 2000],
+            sizes: vec![1; 200],
+        };
+        // C(1801, 200): choose 200 gaps out of the 1801 left once each group has a spacer.
+        let expected = (0u32..200).fold(BigUint::one(), |acc, k| acc * (1801 - k) / (k + 1));
+        assert_eq!(line.arrangements_big(), expected);
+    }
+
+    #[test]
+    fn tables_past_u128() {
+        let line = Line {
+            row: vec![None; 300],
+            sizes: vec![1; 100],
+        };
+        let total = line.arrangements_big();
+        assert!(total.bits() > 128);
+        let (damaged, counted) = line.damage_counts();
+        assert_eq!(counted, total);
+        // By symmetry the damaged counts read the same from either end.
+        assert!(damaged.iter().eq(damaged.iter().rev()));
+        assert_eq!(line.forced().unwrap(), vec![None; 300]);
+        let sample = line.sample(7).unwrap();
+        assert_eq!(
+            sample.iter().filter(|s| **s == Spring::Damaged).count(),
+            100
+        );
+        assert_eq!(
+            render(&line.enumerate().next().unwrap()),
+            ".".repeat(101) + &"#.".repeat(99) + "#"
+        );
+        let p = line.damage_probabilities();
+        assert!(p.iter().all(|p| (0.0..=1.0).contains(p)));
+    }
+}
@@ -1,7 +1,10 @@
+use std::collections::HashSet;
+
+use anyhow::{ensure, Context, Result};
 use aoc_runner_derive::{aoc, aoc_generator};
 use itertools::Itertools;
 #[aoc_generator(day13)]
-fn parse(input: &str) -> Vec<Vec<Vec<bool>>> {
+fn parse(input: &str) -> Result<Vec<Pattern>> {
     input
         .trim()
         .split("\n\n")
@@ -19,173 +22,218 @@ fn parse(input: &str) -> Vec<Vec<Vec<bool>>> {
                 })
                 .collect_vec()
         })
-        .collect_vec()
+        .map(|grid| Pattern::new(&grid))
+        .collect()
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum Axis {
+    // Mirror between two rows.
+    Horizontal,
+    // Mirror between two columns.
+    Vertical,
 }
 
-fn xor(
-    a: impl IntoIterator<Item = bool>,
-    b: impl IntoIterator<Item = bool>,
-) -> impl Iterator<Item = bool> {
-    let a = a.into_iter();
-    let b = b.into_iter();
-    a.zip_eq(b).map(|(a, b)| a ^ b)
+// `index` is how many rows lie above (or columns to the left of) the mirror.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct Reflection {
+    pub axis: Axis,
+    pub index: usize,
 }
 
-fn transpose(grid: &[Vec<bool>]) -> Vec<Vec<bool>> {
-    let mut transposed = Vec::new();
-    for c in 0..grid[0].len() {
-        let mut row = Vec::new();
-        for r in 0..grid.len() {
-            row.push(grid[r][c]);
+impl Reflection {
+    pub fn summary(&self) -> usize {
+        match self.axis {
+            Axis::Horizontal => 100 * self.index,
+            Axis::Vertical => self.index,
         }
-        transposed.push(row);
     }
-    transposed
 }
 
-#[derive(Debug)]
-struct RowCursor<'a> {
-    grid: &'a [Vec<bool>],
-    sum: Vec<bool>,
-    curr: usize,
+// Rows and columns packed into bitmasks: bit c of rows[r] and bit r of cols[c] are both cell
+// (r, c), so comparing two lines is a XOR and a popcount.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Pattern {
+    rows: Vec<u128>,
+    cols: Vec<u128>,
 }
 
-impl<'a> RowCursor<'a> {
-    fn next(self) -> Self {
-        let RowCursor { grid, sum, curr } = self;
-
-        let halfwidth = curr.min(grid.len() - curr);
-        let current_first = curr - halfwidth;
-        let current_last = curr + halfwidth - 1;
-
-        let next = curr + 1;
-        let next_halfwidth = next.min(grid.len() - next);
-        let next_first = next - next_halfwidth;
-        let next_last = next + next_halfwidth - 1;
-
-        let mut sum = sum;
-        for i in current_first..next_first {
-            sum = xor(sum, grid[i].iter().copied()).collect_vec();
-        }
-
-        for i in (current_last + 1)..=next_last {
-            sum = xor(sum, grid[i].iter().copied()).collect_vec();
-        }
-
-        RowCursor {
-            grid,
-            sum,
-            curr: curr + 1,
+impl Pattern {
+    pub fn new(grid: &[Vec<bool>]) -> Result<Self> {
+        let width = grid.first().map_or(0, Vec::len);
+        ensure!(
+            grid.iter().all(|row| row.len() == width),
+            "pattern is not rectangular"
+        );
+        ensure!(
+            width <= 128 && grid.len() <= 128,
+            "pattern is larger than 128x128"
+        );
+        let mut rows = vec![0u128; grid.len()];
+        let mut cols = vec![0u128; width];
+        for (r, row) in grid.iter().enumerate() {
+            for (c, _) in row.iter().enumerate().filter(|(_, rock)| **rock) {
+                rows[r] |= 1 << c;
+                cols[c] |= 1 << r;
+            }
         }
+        Ok(Pattern { rows, cols })
     }
 
-    fn check_mirror(&self) -> bool {
-        let RowCursor { grid, sum, curr } = self;
-        let curr = *curr;
-
-        if sum.iter().any(|x| *x) {
-            return false;
-        }
-
-        let halfwidth = curr.min(grid.len() - curr);
-        let current_first = curr - halfwidth;
-        let current_last = curr + halfwidth - 1;
+    pub fn height(&self) -> usize {
+        self.rows.len()
+    }
 
-        grid[current_first..=current_last]
-            .iter()
-            .rev()
-            .zip_eq(grid[current_first..=current_last].iter())
-            .all(|(a, b)| a == b)
+    pub fn width(&self) -> usize {
+        self.cols.len()
     }
 
-    fn check_smudge(&self) -> bool {
-        let RowCursor { grid, sum, curr } = self;
-        if sum.iter().filter(|x| **x).count() != 1 {
-            return false;
+    pub fn lines(&self, axis: Axis) -> &[u128] {
+        match axis {
+            Axis::Horizontal => &self.rows,
+            Axis::Vertical => &self.cols,
         }
-
-        let curr = *curr;
-        let halfwidth = curr.min(grid.len() - curr);
-        let current_first = curr - halfwidth;
-        let current_last = curr + halfwidth - 1;
-
-        grid[current_first..=current_last]
-            .iter()
-            .rev()
-            .zip_eq(grid[current_first..=current_last].iter())
-            .flat_map(|(a, b)| a.iter().copied().zip_eq(b.iter().copied()))
-            .filter(|(a, b)| a != b)
-            .count()
-            == 2 // (it's 2 because we're double-counting.)
     }
 }
 
-fn find_row_mirror(grid: &[Vec<bool>]) -> Option<usize> {
-    let mut cursor = RowCursor {
-        grid,
-        sum: xor(grid[0].iter().copied(), grid[1].iter().copied()).collect_vec(),
-        curr: 1,
-    };
-
-    while cursor.curr < grid.len() - 1 {
-        if cursor.check_mirror() {
-            return Some(cursor.curr);
+// How many cells differ from their mirror image across a mirror after lines[..index], giving up
+// once past `limit`.
+fn differences(lines: &[u128], index: usize, limit: usize) -> Option<usize> {
+    let mut count = 0;
+    for (a, b) in lines[..index].iter().rev().zip(&lines[index..]) {
+        count += (a ^ b).count_ones() as usize;
+        if count > limit {
+            return None;
         }
-        cursor = cursor.next();
     }
+    Some(count)
+}
 
-    if cursor.check_mirror() {
-        return Some(cursor.curr);
-    }
+// Every mirror line, horizontal ones first, across which exactly `smudges` cells differ from
+// their image.
+pub fn find_reflections(pattern: &Pattern, smudges: usize) -> Vec<Reflection> {
+    [Axis::Horizontal, Axis::Vertical]
+        .into_iter()
+        .flat_map(|axis| {
+            let lines = pattern.lines(axis);
+            (1..lines.len())
+                .filter(move |&index| differences(lines, index, smudges) == Some(smudges))
+                .map(move |index| Reflection { axis, index })
+        })
+        .collect()
+}
 
-    None
+// A cell that differs from its mirror image, as (row, column) of the cell on the near side of
+// the mirror and of its image. Flipping either one fixes it.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct Smudge {
+    pub cell: (usize, usize),
+    pub image: (usize, usize),
 }
 
-fn find_row_smudge(grid: &[Vec<bool>]) -> Option<usize> {
-    let mut cursor = RowCursor {
-        grid,
-        sum: xor(grid[0].iter().copied(), grid[1].iter().copied()).collect_vec(),
-        curr: 1,
-    };
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ReflectionReport {
+    pub reflection: Reflection,
+    pub smudges: Vec<Smudge>,
+}
 
-    while cursor.curr < grid.len() - 1 {
-        if cursor.check_smudge() {
-            return Some(cursor.curr);
-        }
-        cursor = cursor.next();
+impl Pattern {
+    pub fn is_rock(&self, (r, c): (usize, usize)) -> bool {
+        self.rows[r] >> c & 1 == 1
     }
 
-    if cursor.check_smudge() {
-        return Some(cursor.curr);
+    pub fn smudges(&self, reflection: Reflection) -> Vec<Smudge> {
+        let Reflection { axis, index } = reflection;
+        let lines = self.lines(axis);
+        (0..index)
+            .rev()
+            .zip(index..lines.len())
+            .flat_map(|(near, far)| {
+                let diff = lines[near] ^ lines[far];
+                (0..128)
+                    .filter(move |bit| diff >> bit & 1 == 1)
+                    .map(move |bit| match axis {
+                        Axis::Horizontal => Smudge {
+                            cell: (near, bit),
+                            image: (far, bit),
+                        },
+                        Axis::Vertical => Smudge {
+                            cell: (bit, near),
+                            image: (bit, far),
+                        },
+                    })
+            })
+            .sorted()
+            .collect()
     }
 
-    None
+    // Every reflection that needs exactly `smudges` fixes, with where those fixes are. Empty if
+    // the pattern has no such reflection.
+    pub fn report(&self, smudges: usize) -> Vec<ReflectionReport> {
+        find_reflections(self, smudges)
+            .into_iter()
+            .map(|reflection| ReflectionReport {
+                reflection,
+                smudges: self.smudges(reflection),
+            })
+            .collect()
+    }
+
+    // The pattern with the mirror drawn as a row of '-' or a column of '|', and both cells of
+    // each smudge shown as '*'.
+    pub fn render(&self, report: &ReflectionReport) -> String {
+        let Reflection { axis, index } = report.reflection;
+        let smudged = report
+            .smudges
+            .iter()
+            .flat_map(|s| [s.cell, s.image])
+            .collect::<HashSet<_>>();
+        let mut out = String::new();
+        for r in 0..self.height() {
+            if axis == Axis::Horizontal && r == index {
+                out.extend(std::iter::repeat_n('-', self.width()));
+                out.push('\n');
+            }
+            for c in 0..self.width() {
+                if axis == Axis::Vertical && c == index {
+                    out.push('|');
+                }
+                out.push(if smudged.contains(&(r, c)) {
+                    '*'
+                } else if self.is_rock((r, c)) {
+                    '#'
+                } else {
+                    '.'
+                });
+            }
+            out.push('\n');
+        }
+        out
+    }
 }
 
-#[aoc(day13, part1)]
-fn part1(input: &[Vec<Vec<bool>>]) -> usize {
+fn summarize(input: &[Pattern], smudges: usize) -> Result<usize> {
     input
-        .into_iter()
-        .map(|grid| {
-            find_row_mirror(grid)
-                .map(|x| x * 100)
-                .or(find_row_mirror(&transpose(grid)))
-                .expect("never found winning row")
+        .iter()
+        .enumerate()
+        .map(|(i, pattern)| {
+            let reflections = find_reflections(pattern, smudges);
+            let first = reflections.first().with_context(|| {
+                format!("pattern {} has no reflection with {smudges} smudges", i + 1)
+            })?;
+            Ok(first.summary())
         })
         .sum()
 }
 
+#[aoc(day13, part1)]
+fn part1(input: &[Pattern]) -> Result<usize> {
+    summarize(input, 0)
+}
+
 #[aoc(day13, part2)]
-fn part2(input: &[Vec<Vec<bool>>]) -> usize {
-    input
-        .into_iter()
-        .map(|grid| {
-            find_row_smudge(grid)
-                .map(|x| x * 100)
-                .or(find_row_smudge(&transpose(grid)))
-                .expect("never found winning row")
-        })
-        .sum()
+fn part2(input: &[Pattern]) -> Result<usize> {
+    summarize(input, 1)
 }
 
 #[cfg(test)]
@@ -194,16 +242,100 @@ mod tests {
     fn part1sample() {
         eprintln!(
             "{}",
-            super::part1(&super::parse(
-                "#.##..##.
+            super::part1(
+                &super::parse(
+                    "#.##..##.
+..#.##.#.
+##......#
+##......#
+..#.##.#.
+..##..##.
+#.#.##.#.
+"
+                )
+                .unwrap()
+            )
+            .unwrap()
+        );
+    }
+
+    const SAMPLE: &str = "#.##..##.
 ..#.##.#.
 ##......#
 ##......#
 ..#.##.#.
 ..##..##.
 #.#.##.#.
+
+#...##..#
+#....#..#
+..##..###
+#####.##.
+#####.##.
+..##..###
+#....#..#
+";
+
+    #[test]
+    fn sample() {
+        let input = super::parse(SAMPLE).unwrap();
+        assert_eq!(super::part1(&input).unwrap(), 405);
+        assert_eq!(super::part2(&input).unwrap(), 400);
+    }
+
+    #[test]
+    fn smudge_budgets() {
+        use super::*;
+        let pattern = &parse(SAMPLE).unwrap()[0];
+        let vertical = |index| Reflection {
+            axis: Axis::Vertical,
+            index,
+        };
+        assert_eq!(find_reflections(pattern, 0), [vertical(5)]);
+        assert_eq!(
+            find_reflections(pattern, 1),
+            [Reflection {
+                axis: Axis::Horizontal,
+                index: 3
+            }]
+        );
+        // Brute force over every budget: each line has exactly one difference count.
+        let total = (0..=pattern.height() * pattern.width())
+            .map(|k| find_reflections(pattern, k).len())
+            .sum::<usize>();
+        assert_eq!(total, pattern.height() - 1 + pattern.width() - 1);
+    }
+
+    #[test]
+    fn reports() {
+        use super::*;
+        let pattern = &parse(SAMPLE).unwrap()[0];
+        let [report] = pattern.report(1).try_into().unwrap();
+        assert_eq!(
+            report.smudges,
+            [Smudge {
+                cell: (0, 0),
+                image: (5, 0)
+            }]
+        );
+        assert_eq!(
+            pattern.render(&report),
+            "*.##..##.
+..#.##.#.
+##......#
+---------
+##......#
+..#.##.#.
+*.##..##.
+#.#.##.#.
 "
-            ))
         );
+        let [clean] = pattern.report(0).try_into().unwrap();
+        assert!(clean.smudges.is_empty());
+        assert!(pattern.render(&clean).starts_with("#.##.|.##.\n"));
+
+        let no_mirror = parse("#.\n##\n").unwrap();
+        assert!(no_mirror[0].report(0).is_empty());
+        assert!(part1(&no_mirror).is_err());
     }
 }
@@ -1,183 +1,361 @@
-use std::collections::HashMap;
-
+use anyhow::{bail, ensure, Result};
 use aoc_runner_derive::{aoc, aoc_generator};
+use itertools::Itertools;
+
+use crate::cycle::{Cycle, History};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
-enum Rock {
-    Round,
-    Cube,
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum Dir {
+    N,
+    E,
+    S,
+    W,
 }
 
-#[aoc_generator(day14)]
-fn parse(input: &str) -> (HashMap<(usize, usize), Rock>, usize, usize) {
-    let num_rows = input.lines().count();
-    let num_cols = input.lines().next().unwrap().chars().count();
-    (
-        input
-            .lines()
-            .enumerate()
-            .flat_map(|(r, line)| {
-                line.char_indices().flat_map(move |(c, rock)| {
-                    let rock = match rock {
-                        '#' => Some(Rock::Cube),
-                        'O' => Some(Rock::Round),
-                        '.' => None,
-                        _ => panic!("{rock}"),
-                    };
-                    rock.map(|rock| ((r, c), rock))
+impl Dir {
+    // A tilt sequence such as "NWSE".
+    pub fn parse_sequence(s: &str) -> Result<Vec<Dir>> {
+        s.chars()
+            .map(|c| {
+                Ok(match c {
+                    'N' => Dir::N,
+                    'E' => Dir::E,
+                    'S' => Dir::S,
+                    'W' => Dir::W,
+                    _ => bail!("unknown tilt direction {c:?}"),
                 })
             })
-            .collect(),
-        num_rows,
-        num_cols,
-    )
+            .collect()
+    }
 }
 
-#[aoc(day14, part1)]
-fn part1(input: &(HashMap<(usize, usize), Rock>, usize, usize)) -> usize {
-    let (input, num_rows, num_cols) = input;
-    let mut sum = 0;
-    for c in 0..*num_cols {
-        let mut next_empty = 0;
-        for r in 0..*num_rows {
-            match input.get(&(r, c)) {
-                Some(rock) => match rock {
-                    Rock::Round => {
-                        sum += *num_rows - next_empty;
-                        next_empty += 1;
-                    }
-                    Rock::Cube => {
-                        next_empty = r + 1;
-                    }
-                },
-                None => {}
-            }
+pub const SPIN_CYCLE: [Dir; 4] = [Dir::N, Dir::W, Dir::S, Dir::E];
+
+// Bits start..start + len.
+fn span(start: usize, len: usize) -> u128 {
+    if len == 0 {
+        0
+    } else {
+        u128::MAX >> (128 - len) << start
+    }
+}
+
+// The maximal runs of non-cube cells in each line, as (start, len).
+fn segments(cubes: &[u128], len: usize) -> Vec<Vec<(usize, usize)>> {
+    cubes
+        .iter()
+        .map(|line| {
+            (0..len)
+                .group_by(|i| line >> i & 1 == 1)
+                .into_iter()
+                .filter(|(cube, _)| !cube)
+                .map(|(_, run)| {
+                    let run = run.collect_vec();
+                    (run[0], run.len())
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn transpose(lines: &[u128], len: usize) -> Vec<u128> {
+    let mut transposed = vec![0u128; len];
+    for (i, line) in lines.iter().enumerate() {
+        let mut bits = *line;
+        while bits != 0 {
+            let j = bits.trailing_zeros() as usize;
+            transposed[j] |= 1 << i;
+            bits &= bits - 1;
         }
     }
-    sum
+    transposed
 }
 
-fn cycle(grid: &mut HashMap<(usize, usize), Rock>, num_rows: usize, num_cols: usize) {
-    // north
-    for c in 0..num_cols {
-        let mut next_empty = 0;
-        for r in 0..num_rows {
-            match grid.remove(&(r, c)) {
-                Some(rock) => match rock {
-                    Rock::Round => {
-                        assert!(grid.insert((next_empty, c), Rock::Round).is_none());
-                        next_empty += 1;
-                    }
-                    Rock::Cube => {
-                        assert!(grid.insert((r, c), Rock::Cube).is_none());
-                        next_empty = r + 1;
-                    }
-                },
-                None => {}
+// Round rocks as one bitset per row (bit c is column c). Tilting packs the round rocks in each
+// run between cubes against one end of the run, which only needs a popcount per run; north and
+// south tilts work on the transposed, column-major bitsets.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct Platform {
+    height: usize,
+    width: usize,
+    rounds: Vec<u128>,
+    cubes: Vec<u128>,
+    row_segments: Vec<Vec<(usize, usize)>>,
+    col_segments: Vec<Vec<(usize, usize)>>,
+}
+
+impl Platform {
+    pub fn parse(input: &str) -> Result<Self> {
+        let height = input.lines().count();
+        let width = input.lines().next().map_or(0, |line| line.chars().count());
+        ensure!(
+            height <= 128 && width <= 128,
+            "platform is larger than 128x128"
+        );
+        let mut rounds = vec![0u128; height];
+        let mut cubes = vec![0u128; height];
+        for (r, line) in input.lines().enumerate() {
+            ensure!(line.chars().count() == width, "platform is not rectangular");
+            for (c, rock) in line.chars().enumerate() {
+                match rock {
+                    'O' => rounds[r] |= 1 << c,
+                    '#' => cubes[r] |= 1 << c,
+                    '.' => {}
+                    _ => bail!("unknown tile {rock:?}"),
+                }
             }
         }
+        Ok(Platform {
+            height,
+            width,
+            row_segments: segments(&cubes, width),
+            col_segments: segments(&transpose(&cubes, width), height),
+            rounds,
+            cubes,
+        })
     }
 
-    // west
-    for r in 0..num_rows {
-        let mut next_empty = 0;
-        for c in 0..num_cols {
-            match grid.remove(&(r, c)) {
-                Some(rock) => match rock {
-                    Rock::Round => {
-                        assert!(grid.insert((r, next_empty), Rock::Round).is_none());
-                        next_empty += 1;
-                    }
-                    Rock::Cube => {
-                        assert!(grid.insert((r, c), Rock::Cube).is_none());
-                        next_empty = c + 1;
-                    }
-                },
-                None => {}
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    fn pack(lines: &mut [u128], segments: &[Vec<(usize, usize)>], toward_start: bool) {
+        for (line, segments) in lines.iter_mut().zip(segments) {
+            let mut packed = 0;
+            for &(start, len) in segments {
+                let count = (*line & span(start, len)).count_ones() as usize;
+                packed |= if toward_start {
+                    span(start, count)
+                } else {
+                    span(start + len - count, count)
+                };
             }
+            *line = packed;
         }
     }
 
-    // south
-    for c in 0..num_cols {
-        let mut next_empty = num_rows - 1;
-        for r in (0..num_rows).rev() {
-            match grid.remove(&(r, c)) {
-                Some(rock) => match rock {
-                    Rock::Round => {
-                        assert!(grid.insert((next_empty, c), Rock::Round).is_none());
-                        next_empty -= 1;
-                    }
-                    Rock::Cube => {
-                        assert!(grid.insert((r, c), Rock::Cube).is_none());
-                        next_empty = r - 1;
-                    }
-                },
-                None => {}
+    // Tilts a set of round rocks on this platform's cubes, leaving the platform itself alone.
+    fn tilt_rounds(&self, rounds: &mut Vec<u128>, dir: Dir) {
+        match dir {
+            Dir::W | Dir::E => Self::pack(rounds, &self.row_segments, dir == Dir::W),
+            Dir::N | Dir::S => {
+                let mut cols = transpose(rounds, self.width);
+                Self::pack(&mut cols, &self.col_segments, dir == Dir::N);
+                *rounds = transpose(&cols, self.height);
             }
         }
     }
 
-    // east
-    for r in 0..num_rows {
-        let mut next_empty = num_cols - 1;
-        for c in (0..num_cols).rev() {
-            match grid.remove(&(r, c)) {
-                Some(rock) => match rock {
-                    Rock::Round => {
-                        assert!(grid.insert((r, next_empty), Rock::Round).is_none());
-                        next_empty -= 1;
-                    }
-                    Rock::Cube => {
-                        assert!(grid.insert((r, c), Rock::Cube).is_none());
-                        next_empty = c - 1;
-                    }
-                },
-                None => {}
+    pub fn tilt(&mut self, dir: Dir) {
+        let mut rounds = std::mem::take(&mut self.rounds);
+        self.tilt_rounds(&mut rounds, dir);
+        self.rounds = rounds;
+    }
+
+    pub fn tilt_sequence(&mut self, dirs: &[Dir]) {
+        for dir in dirs {
+            self.tilt(*dir);
+        }
+    }
+
+    pub fn spin_cycle(&mut self) {
+        self.tilt_sequence(&SPIN_CYCLE);
+    }
+
+    pub fn north_load(&self) -> usize {
+        self.north_load_of(&self.rounds)
+    }
+
+    // Each round rock counts its distance from the south edge, counting its own row.
+    fn north_load_of(&self, rounds: &[u128]) -> usize {
+        rounds
+            .iter()
+            .enumerate()
+            .map(|(r, row)| row.count_ones() as usize * (self.height - r))
+            .sum()
+    }
+
+    pub fn render(&self) -> String {
+        (0..self.height)
+            .map(|r| {
+                (0..self.width)
+                    .map(
+                        |c| match (self.rounds[r] >> c & 1, self.cubes[r] >> c & 1) {
+                            (1, _) => 'O',
+                            (_, 1) => '#',
+                            _ => '.',
+                        },
+                    )
+                    .chain(std::iter::once('\n'))
+                    .collect::<String>()
+            })
+            .collect()
+    }
+}
+
+#[aoc_generator(day14)]
+fn parse(input: &str) -> Result<Platform> {
+    Platform::parse(input)
+}
+
+#[aoc(day14, part1)]
+fn part1(input: &Platform) -> usize {
+    let mut platform = input.clone();
+    platform.tilt(Dir::N);
+    platform.north_load()
+}
+
+// The north load after 0, 1, 2, ... spin cycles, through the first full period of the cycle.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct LoadHistory {
+    loads: Vec<usize>,
+    cycle: Cycle,
+}
+
+impl LoadHistory {
+    // Only the round rocks change from spin to spin, so they alone are the state that is hashed
+    // and kept; the cubes and their runs are shared from `platform`.
+    pub fn new(platform: &Platform) -> Self {
+        let history = History::detect(platform.rounds.clone(), |rounds| {
+            let mut rounds = rounds.clone();
+            for dir in SPIN_CYCLE {
+                platform.tilt_rounds(&mut rounds, dir);
             }
+            rounds
+        });
+        LoadHistory {
+            loads: history
+                .states()
+                .iter()
+                .map(|rounds| platform.north_load_of(rounds))
+                .collect(),
+            cycle: history.cycle(),
         }
     }
+
+    pub fn cycle(&self) -> Cycle {
+        self.cycle
+    }
+
+    // The prefix's loads followed by one period's.
+    pub fn loads(&self) -> &[usize] {
+        &self.loads
+    }
+
+    pub fn after(&self, spins: usize) -> usize {
+        self.loads[self.cycle.reduce(spins)]
+    }
+
+    pub fn after_each(&self, spins: &[usize]) -> Vec<usize> {
+        spins.iter().map(|n| self.after(*n)).collect()
+    }
+}
+
+pub fn load_after(platform: &Platform, spins: usize) -> usize {
+    LoadHistory::new(platform).after(spins)
 }
 
 #[aoc(day14, part2)]
-fn part2(input: &(HashMap<(usize, usize), Rock>, usize, usize)) -> usize {
-    let (input, num_rows, num_cols) = input;
-    let num_rows = *num_rows;
-    let num_cols = *num_cols;
-
-    let mut tortoise = input.clone();
-    let mut hare = input.clone();
-
-    let mut period_start = None;
-    let mut now = 0usize;
-    let period_end = loop {
-        if tortoise == hare {
-            match period_start {
-                Some(_) => break now,
-                None => {
-                    assert!(period_start.replace(now).is_none());
-                }
-            }
+fn part2(input: &Platform) -> usize {
+    load_after(input, 1000000000)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = "O....#....
+O.OO#....#
+.....##...
+OO.#O....O
+.O.....O#.
+O.#..O.#.#
+..O..#O..O
+.......O..
+#....###..
+#OO..#....
+";
+
+    #[test]
+    fn sample() {
+        let input = parse(SAMPLE).unwrap();
+        assert_eq!(part1(&input), 136);
+        assert_eq!(part2(&input), 64);
+    }
+
+    #[test]
+    fn spin_cycles() {
+        let mut platform = parse(SAMPLE).unwrap();
+        platform.spin_cycle();
+        assert_eq!(
+            platform.render(),
+            ".....#....
+....#...O#
+...OO##...
+.OO#......
+.....OOO#.
+.O#...O#.#
+....O#....
+......OOOO
+#...O###..
+#..OO#....
+"
+        );
+        platform.tilt_sequence(&Dir::parse_sequence("NWSE").unwrap());
+        assert_eq!(
+            platform.render(),
+            ".....#....
+....#...O#
+.....##...
+..O#......
+.....OOO#.
+.O#...O#.#
+....O#...O
+.......OOO
+#..OO###..
+#.OOO#...O
+"
+        );
+        assert!(Dir::parse_sequence("NX").is_err());
+    }
+
+    #[test]
+    fn tilts_keep_rocks() {
+        let mut platform = parse(SAMPLE).unwrap();
+        let count = |p: &Platform| p.render().matches('O').count();
+        let cubes = platform.render().replace('O', ".");
+        let before = count(&platform);
+        for dir in Dir::parse_sequence("SSENWWNE").unwrap() {
+            platform.tilt(dir);
+            assert_eq!(count(&platform), before);
+            assert_eq!(platform.render().replace('O', "."), cubes);
         }
-        now += 1;
-        cycle(&mut tortoise, num_rows, num_cols);
-        cycle(&mut hare, num_rows, num_cols);
-        cycle(&mut hare, num_rows, num_cols);
-    };
-    let period_start = period_start.unwrap();
-    let period = period_end - period_start;
-    assert!(period_end < 1000000000);
-    let remaining_cycles = (1000000000 - period_end) % period;
-
-    for _ in 0..remaining_cycles {
-        cycle(&mut tortoise, num_rows, num_cols);
-    }
-
-    tortoise
-        .into_iter()
-        .filter_map(|((r, _), rock)| match rock {
-            Rock::Round => Some(num_rows - r),
-            Rock::Cube => None,
-        })
-        .sum()
+        // Tilting twice the same way changes nothing the second time.
+        let tilted = platform.clone();
+        platform.tilt(Dir::E);
+        assert_eq!(platform, tilted);
+    }
+
+    #[test]
+    fn load_history() {
+        let platform = parse(SAMPLE).unwrap();
+        let history = LoadHistory::new(&platform);
+        let Cycle { prefix, period } = history.cycle();
+        assert_eq!(history.loads().len(), prefix + period);
+
+        let mut spun = platform.clone();
+        let mut direct = Vec::new();
+        for _ in 0..=3 * (prefix + period) {
+            direct.push(spun.north_load());
+            spun.spin_cycle();
+        }
+        let targets = (0..direct.len()).collect_vec();
+        assert_eq!(history.after_each(&targets), direct);
+        assert_eq!(history.after(1000000000), 64);
+        assert_eq!(load_after(&platform, 1), 87);
+    }
 }
@@ -3,6 +3,8 @@ use std::collections::HashMap;
 use aoc_runner_derive::{aoc, aoc_generator};
 use itertools::Itertools;
 
+use crate::intervals::{Interval, IntervalBox};
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 struct Part {
     x: i64,
@@ -179,102 +181,33 @@ fn part1(input: &(Vec<Workflow>, Vec<Part>)) -> i64 {
         .sum()
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-struct PartBounds {
-    x: (i64, i64),
-    m: (i64, i64),
-    a: (i64, i64),
-    s: (i64, i64),
-}
-
-impl PartBounds {
-    fn count(&self) -> u64 {
-        let Self { x, m, a, s } = self;
-        [x, m, a, s]
-            .into_iter()
-            .map(|(lo, hi)| {
-                let lo = *lo;
-                let hi = *hi;
-                u64::try_from(hi - lo).unwrap()
-            })
-            .product()
-    }
-
-    fn split(
-        &self,
-        Matcher {
-            cmp_dir,
-            category,
-            threshold,
-        }: Matcher,
-    ) -> (Option<Self>, Option<Self>) {
-        let Self { x, m, a, s } = *self;
+type PartBounds = IntervalBox<4>;
 
-        match category {
-            Category::X => {
-                let (matched, nomatched) = split_bound(x, cmp_dir, threshold);
-                (
-                    matched.map(|x| Self { x, ..*self }),
-                    nomatched.map(|x| Self { x, ..*self }),
-                )
-            }
-            Category::M => {
-                let (matched, nomatched) = split_bound(m, cmp_dir, threshold);
-                (
-                    matched.map(|m| Self { m, ..*self }),
-                    nomatched.map(|m| Self { m, ..*self }),
-                )
-            }
-            Category::A => {
-                let (matched, nomatched) = split_bound(a, cmp_dir, threshold);
-                (
-                    matched.map(|a| Self { a, ..*self }),
-                    nomatched.map(|a| Self { a, ..*self }),
-                )
-            }
-            Category::S => {
-                let (matched, nomatched) = split_bound(s, cmp_dir, threshold);
-                (
-                    matched.map(|s| Self { s, ..*self }),
-                    nomatched.map(|s| Self { s, ..*self }),
-                )
-            }
+impl Category {
+    fn axis(self) -> usize {
+        match self {
+            Category::X => 0,
+            Category::M => 1,
+            Category::A => 2,
+            Category::S => 3,
         }
     }
 }
 
-fn is_nonempty((lo, hi): (i64, i64)) -> bool {
-    lo < hi
-}
-
-fn split_bound(
-    (lo, hi): (i64, i64),
-    cmp_dir: CmpDir,
-    threshold: i64,
-) -> (Option<(i64, i64)>, Option<(i64, i64)>) {
+// (matching, not matching)
+fn split_bounds(
+    bounds: &PartBounds,
+    Matcher {
+        cmp_dir,
+        category,
+        threshold,
+    }: Matcher,
+) -> (Option<PartBounds>, Option<PartBounds>) {
     match cmp_dir {
-        CmpDir::Less => {
-            let lomatch = lo;
-            let himatch = hi.min(threshold);
-            // to not match, lo must be >=.
-            let lonomatch = lo.max(threshold);
-            let hinomatch = hi;
-            (
-                Some((lomatch, himatch)).filter(|x| is_nonempty(*x)),
-                Some((lonomatch, hinomatch)).filter(|x| is_nonempty(*x)),
-            )
-        }
+        CmpDir::Less => bounds.split_at(category.axis(), threshold),
         CmpDir::Greater => {
-            let lomatch = lo.max(threshold + 1);
-            let himatch = hi;
-
-            let lonomatch = lo;
-            let hinomatch = hi.min(threshold + 1);
-
-            (
-                Some((lomatch, himatch)).filter(|x| is_nonempty(*x)),
-                Some((lonomatch, hinomatch)).filter(|x| is_nonempty(*x)),
-            )
+            let (lo, hi) = bounds.split_at(category.axis(), threshold + 1);
+            (hi, lo)
         }
     }
 }
@@ -314,13 +247,13 @@ fn count_solns_impl(
     let Rule { matcher, action } = &curr_rules[*step];
     match matcher {
         Some(matcher) => {
-            let (matched, nomatched) = bounds.split(*matcher);
+            let (matched, nomatched) = split_bounds(bounds, *matcher);
 
             let mut sum = 0u64;
             if let Some(matched) = matched {
                 match action {
                     Action::Accept => {
-                        sum += matched.count();
+                        sum += matched.volume();
                     }
                     Action::Reject => {
                         // 0.
@@ -362,7 +295,7 @@ fn count_solns_impl(
                     curr_workflow: (workflow.to_owned(), 0),
                 },
             ),
-            Action::Accept => bounds.count(),
+            Action::Accept => bounds.volume(),
             Action::Reject => 0u64,
         },
     }
@@ -376,12 +309,7 @@ fn part2(input: &(Vec<Workflow>, Vec<Part>)) -> u64 {
         .map(|workflow| (workflow.name.clone(), workflow.rules.clone()))
         .collect::<HashMap<_, _>>();
 
-    let bounds = PartBounds {
-        x: (1, 4001),
-        m: (1, 4001),
-        a: (1, 4001),
-        s: (1, 4001),
-    };
+    let bounds = PartBounds::new([Interval::new(1, 4001); 4]);
 
     let mut table = HashMap::new();
     count_solns(
@@ -393,3 +321,34 @@ fn part2(input: &(Vec<Workflow>, Vec<Part>)) -> u64 {
         },
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sample() {
+        let input = parse(
+            "px{a<2006:qkq,m>2090:A,rfg}
+pv{a>1716:R,A}
+lnx{m>1548:A,A}
+rfg{s<537:gd,x>2440:R,A}
+qs{s>3448:A,lnx}
+qkq{x<1416:A,crn}
+crn{x>2662:A,R}
+in{s<1351:px,qqz}
+qqz{s>2770:qs,m<1801:hdj,R}
+gd{a>3333:R,R}
+hdj{m>838:A,pv}
+
+{x=787,m=2655,a=1222,s=2876}
+{x=1679,m=44,a=2067,s=496}
+{x=2036,m=264,a=79,s=2244}
+{x=2461,m=1339,a=466,s=291}
+{x=2127,m=1623,a=2188,s=1013}
+",
+        );
+        assert_eq!(part1(&input), 19114);
+        assert_eq!(part2(&input), 167409079868000);
+    }
+}
@@ -1,65 +1,153 @@
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 
-use anyhow::Context;
+use anyhow::{Context, Result};
 use aoc_runner_derive::{aoc, aoc_generator};
-use itertools::Itertools;
-#[aoc_generator(day2)]
-fn parse(input: &str) -> Vec<Vec<Vec<(i32, String)>>> {
-    input
-        .lines()
-        .map(|line| {
-            let (_, wo_pref) = line.split_once(": ").unwrap();
-            let game_turns: Vec<Vec<(i32, String)>> = wo_pref
-                .split("; ")
-                .map(|turn| {
-                    turn.split(", ")
-                        .map(|spec| {
-                            let (n, color) = spec.split_once(" ").unwrap();
-                            (
-                                n.parse::<i32>().context(wo_pref.to_string()).unwrap(),
-                                color.to_string(),
-                            )
-                        })
-                        .collect::<Vec<_>>()
-                })
-                .collect::<Vec<_>>();
-            game_turns
-        })
-        .collect::<Vec<_>>()
+
+pub type Bag = BTreeMap<String, u32>;
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Draw {
+    pub counts: BTreeMap<String, u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Game {
+    pub id: u32,
+    pub draws: Vec<Draw>,
 }
 
-fn possible(game: &[Vec<(i32, String)>], bag: &HashMap<&str, i32>) -> bool {
-    game.iter().all(|handful| {
-        handful
+impl Draw {
+    fn parse(s: &str) -> Result<Self> {
+        let mut counts = BTreeMap::new();
+        for spec in s.split(", ") {
+            let (n, colour) = spec.split_once(' ').context(spec.to_string())?;
+            let n = n.parse::<u32>().context(spec.to_string())?;
+            *counts.entry(colour.to_string()).or_default() += n;
+        }
+        Ok(Draw { counts })
+    }
+
+    pub fn fits_in(&self, bag: &Bag) -> bool {
+        self.counts
             .iter()
-            .all(|(n, color)| *n <= *bag.get(color.as_str()).unwrap())
-    })
+            .all(|(colour, n)| *n <= bag.get(colour).copied().unwrap_or(0))
+    }
+}
+
+impl Game {
+    pub fn parse(line: &str) -> Result<Self> {
+        let (prefix, draws) = line.split_once(": ").context(line.to_string())?;
+        let id = prefix
+            .strip_prefix("Game ")
+            .context(line.to_string())?
+            .parse::<u32>()
+            .context(line.to_string())?;
+        let draws = draws
+            .split("; ")
+            .map(Draw::parse)
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Game { id, draws })
+    }
+
+    // Colours missing from the bag count as zero cubes.
+    pub fn is_possible_with(&self, bag: &Bag) -> bool {
+        self.draws.iter().all(|draw| draw.fits_in(bag))
+    }
+
+    pub fn min_bag(&self) -> Bag {
+        min_bag(std::slice::from_ref(self))
+    }
+
+    // Restricted to `colours`, with a zero entry for any colour the game never drew.
+    pub fn min_bag_for(&self, colours: &[&str]) -> Bag {
+        let observed = self.min_bag();
+        colours
+            .iter()
+            .map(|c| (c.to_string(), observed.get(*c).copied().unwrap_or(0)))
+            .collect()
+    }
+}
+
+pub fn power(bag: &Bag) -> u32 {
+    bag.values().product()
+}
+
+// The smallest bag every game could have been played with.
+pub fn min_bag(games: &[Game]) -> Bag {
+    let mut bag = Bag::new();
+    for draw in games.iter().flat_map(|game| &game.draws) {
+        for (colour, n) in &draw.counts {
+            let entry = bag.entry(colour.clone()).or_default();
+            *entry = (*entry).max(*n);
+        }
+    }
+    bag
+}
+
+pub fn possible_games<'a>(games: &'a [Game], bag: &'a Bag) -> impl Iterator<Item = &'a Game> {
+    games.iter().filter(|game| game.is_possible_with(bag))
+}
+
+pub fn consistent_bags<'a>(games: &[Game], bags: &'a [Bag]) -> Vec<&'a Bag> {
+    bags.iter()
+        .filter(|bag| games.iter().all(|game| game.is_possible_with(bag)))
+        .collect()
+}
+
+#[aoc_generator(day2)]
+fn parse(input: &str) -> Result<Vec<Game>> {
+    input.lines().map(Game::parse).collect()
 }
 
 #[aoc(day2, part1)]
-fn part1(input: &[Vec<Vec<(i32, String)>>]) -> usize {
-    let bag = HashMap::from([("red", 12), ("green", 13), ("blue", 14)]);
-    input
-        .iter()
-        .enumerate()
-        .filter_map(|(i, game)| possible(game, &bag).then_some(i + 1))
-        .sum()
+fn part1(input: &[Game]) -> u32 {
+    let bag = Bag::from([
+        ("red".to_string(), 12),
+        ("green".to_string(), 13),
+        ("blue".to_string(), 14),
+    ]);
+    possible_games(input, &bag).map(|game| game.id).sum()
 }
 
 #[aoc(day2, part2)]
-fn part2(input: &[Vec<Vec<(i32, String)>>]) -> i32 {
-    input
-        .iter()
-        .map(|game| {
-            let grouped = game
-                .iter()
-                .flatten()
-                .into_grouping_map_by(|(_count, color)| color)
-                .max_by_key(|_k, (v, _color)| *v);
-            grouped
-                .into_iter()
-                .map(|(_key, (n, _color))| n)
-                .product::<i32>()
-        })
-        .sum()
+fn part2(input: &[Game]) -> u32 {
+    input.iter().map(|game| power(&game.min_bag())).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = "Game 1: 3 blue, 4 red; 1 red, 2 green, 6 blue; 2 green
+Game 2: 1 blue, 2 green; 3 green, 4 blue, 1 red; 1 green, 1 blue
+Game 3: 8 green, 6 blue, 20 red; 5 blue, 4 red, 13 green; 5 green, 1 red
+Game 4: 1 green, 3 red, 6 blue; 3 green, 6 red; 3 green, 15 blue, 14 red
+Game 5: 6 red, 1 blue, 3 green; 2 blue, 1 red, 2 green
+";
+
+    #[test]
+    fn sample() {
+        let games = parse(SAMPLE).unwrap();
+        assert_eq!(part1(&games), 8);
+        assert_eq!(part2(&games), 2286);
+    }
+
+    #[test]
+    fn non_contiguous_ids_and_bag_queries() {
+        let games = parse("Game 7: 2 red, 1 blue\nGame 42: 5 red; 3 yellow\n").unwrap();
+        let small = Bag::from([("red".to_string(), 5), ("blue".to_string(), 1)]);
+        assert_eq!(
+            possible_games(&games, &small)
+                .map(|g| g.id)
+                .collect::<Vec<_>>(),
+            [7]
+        );
+
+        let big = min_bag(&games);
+        assert_eq!(consistent_bags(&games, &[small, big.clone()]), [&big]);
+        assert_eq!(
+            games[1].min_bag_for(&["red", "green"]),
+            Bag::from([("red".to_string(), 5), ("green".to_string(), 0)])
+        );
+    }
 }
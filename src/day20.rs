@@ -1,9 +1,12 @@
 use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 
+use anyhow::{ensure, Context, Result};
 use aoc_runner_derive::{aoc, aoc_generator};
 use itertools::Itertools;
 use num::integer::lcm;
 
+use crate::cycle::brent;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 enum Pulse {
     High,
@@ -142,57 +145,63 @@ fn parse(input: &str) -> BTreeMap<String, Module> {
     modules
 }
 
-#[aoc(day20, part1)]
-fn part1(input: &BTreeMap<String, Module>) -> usize {
-    let mut input = input.clone();
-    let mut low_pulses = 0usize;
-    let mut high_pulses = 0usize;
-
+// Pushes the button once and runs every pulse to completion, calling `observe` with (source,
+// destination, pulse) for each in the order they are processed.
+fn press_button(
+    modules: &mut BTreeMap<String, Module>,
+    mut observe: impl FnMut(&str, &str, Pulse),
+) {
     let mut pulse_queue = VecDeque::<(String, String, Pulse)>::new();
+    pulse_queue.push_back((BUTTON.to_owned(), BROADCASTER.to_owned(), Pulse::Low));
 
-    for _ in 1..=1000 {
-        pulse_queue.push_back((BUTTON.to_owned(), BROADCASTER.to_owned(), Pulse::Low));
-
-        while let Some((src, dest, pulse)) = pulse_queue.pop_front() {
-            match pulse {
-                Pulse::High => high_pulses += 1,
-                Pulse::Low => low_pulses += 1,
-            }
-            let Some(module) = input.get_mut(&dest) else {
-                continue
-            };
-            let me = dest;
-            match &mut module.ty {
-                ModuleType::FlipFlop(flipflop) => {
-                    let pulse = flipflop.pulse(pulse);
-                    if let Some(pulse) = pulse {
-                        for dest in &module.dests {
-                            pulse_queue.push_back((me.clone(), dest.clone(), pulse));
-                        }
-                    }
-                }
-                ModuleType::Conjunction(conj) => {
-                    let pulse = conj.pulse(src, pulse);
+    while let Some((src, dest, pulse)) = pulse_queue.pop_front() {
+        observe(&src, &dest, pulse);
+        let Some(module) = modules.get_mut(&dest) else {
+            continue;
+        };
+        let me = dest;
+        match &mut module.ty {
+            ModuleType::FlipFlop(flipflop) => {
+                let pulse = flipflop.pulse(pulse);
+                if let Some(pulse) = pulse {
                     for dest in &module.dests {
                         pulse_queue.push_back((me.clone(), dest.clone(), pulse));
                     }
                 }
-                ModuleType::Broadcast => {
-                    for dest in &module.dests {
-                        pulse_queue.push_back((me.clone(), dest.clone(), pulse));
-                    }
+            }
+            ModuleType::Conjunction(conj) => {
+                let pulse = conj.pulse(src, pulse);
+                for dest in &module.dests {
+                    pulse_queue.push_back((me.clone(), dest.clone(), pulse));
+                }
+            }
+            ModuleType::Broadcast => {
+                for dest in &module.dests {
+                    pulse_queue.push_back((me.clone(), dest.clone(), pulse));
                 }
             }
         }
     }
+}
+
+#[aoc(day20, part1)]
+fn part1(input: &BTreeMap<String, Module>) -> usize {
+    let mut input = input.clone();
+    let mut low_pulses = 0usize;
+    let mut high_pulses = 0usize;
+
+    for _ in 1..=1000 {
+        press_button(&mut input, |_, _, pulse| match pulse {
+            Pulse::High => high_pulses += 1,
+            Pulse::Low => low_pulses += 1,
+        });
+    }
 
     low_pulses * high_pulses
 }
 
 #[aoc(day20, part2)]
-fn part2(input: &BTreeMap<String, Module>) -> usize {
-    let mut input = input.clone();
-
+fn part2(input: &BTreeMap<String, Module>) -> Result<usize> {
     let reversegraph = {
         input
             .iter()
@@ -206,59 +215,103 @@ fn part2(input: &BTreeMap<String, Module>) -> usize {
             .collect::<HashSet<_>>()
     };
 
-    // "kc" is the conjunction component right before "rx" in my input.
-    let senders_to_kc = reversegraph.get("kc").unwrap();
-    let mut sent_hi_to_kc = HashMap::new();
-
-    let mut pulse_queue = VecDeque::<(String, String, Pulse)>::new();
+    // A single conjunction feeds "rx", so rx gets a low pulse once every input to that
+    // conjunction has just sent it a high one.
+    let feeder = reversegraph
+        .get("rx")
+        .and_then(|inputs| inputs.iter().exactly_one().ok())
+        .context("rx does not have exactly one input")?;
 
-    for num_pulses in 1usize.. {
-        if sent_hi_to_kc.len() == senders_to_kc.len() {
-            break;
-        }
-
-        pulse_queue.push_back((BUTTON.to_owned(), BROADCASTER.to_owned(), Pulse::Low));
-
-        while let Some((src, dest, pulse)) = pulse_queue.pop_front() {
-            match pulse {
-                Pulse::High => {
-                    if &dest == "kc" {
-                        sent_hi_to_kc.entry(src.clone()).or_insert(num_pulses);
+    // Each input is driven by its own sub-circuit, which evolves independently of the others.
+    // Rather than assume those sub-circuits are periodic, find each one's cycle on its own state
+    // and check that it fires exactly once per period, on the press that returns it to the start.
+    reversegraph
+        .get(feeder)
+        .context("rx's input has no inputs")?
+        .iter()
+        .map(|sender| {
+            let mut upstream = HashSet::from([sender.clone()]);
+            let mut frontier = vec![sender.clone()];
+            while let Some(name) = frontier.pop() {
+                for source in reversegraph.get(&name).into_iter().flatten() {
+                    if upstream.insert(source.clone()) {
+                        frontier.push(source.clone());
                     }
                 }
-                Pulse::Low => {}
             }
+            let circuit = input
+                .iter()
+                .filter(|(name, _)| upstream.contains(*name))
+                .map(|(name, module)| (name.clone(), module.clone()))
+                .collect::<BTreeMap<_, _>>();
 
-            let Some(module) = input.get_mut(&dest) else {
-                continue
-            };
-            let me = dest;
-            match &mut module.ty {
-                ModuleType::FlipFlop(flipflop) => {
-                    let pulse = flipflop.pulse(pulse);
-                    if let Some(pulse) = pulse {
-                        for dest in &module.dests {
-                            pulse_queue.push_back((me.clone(), dest.clone(), pulse));
-                        }
-                    }
-                }
-                ModuleType::Conjunction(conj) => {
-                    let pulse = conj.pulse(src, pulse);
-                    for dest in &module.dests {
-                        pulse_queue.push_back((me.clone(), dest.clone(), pulse));
+            let cycle = brent(&circuit, |circuit| {
+                let mut circuit = circuit.clone();
+                press_button(&mut circuit, |_, _, _| {});
+                circuit
+            });
+            let mut circuit = circuit;
+            let mut fires = Vec::new();
+            for press in 1..=cycle.prefix + cycle.period {
+                press_button(&mut circuit, |src, dest, pulse| {
+                    if src == sender && dest == feeder && pulse == Pulse::High {
+                        fires.push(press);
                     }
-                }
-                ModuleType::Broadcast => {
-                    for dest in &module.dests {
-                        pulse_queue.push_back((me.clone(), dest.clone(), pulse));
-                    }
-                }
+                });
             }
-        }
+            ensure!(
+                cycle.prefix == 0 && fires == [cycle.period],
+                "{sender} is not a counter that fires as it resets ({cycle:?}, fires at {fires:?})"
+            );
+            Ok(cycle.period)
+        })
+        .fold_ok(1usize, lcm)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sample() {
+        let input = parse(
+            "broadcaster -> a, b, c
+%a -> b
+%b -> c
+%c -> inv
+&inv -> a
+",
+        );
+        assert_eq!(part1(&input), 32000000);
     }
 
-    // This, again, doesn't seem like something that should be strictly right unless
-    // we always loop back to the starting condition, but it worked for the ghosts
-    // question before.
-    sent_hi_to_kc.values().copied().fold(1usize, lcm)
+    #[test]
+    fn counters() {
+        // A two-press counter into ca and a four-press counter into cc.
+        let input = parse(
+            "broadcaster -> a, b
+%a -> ca
+&ca -> kc
+%b -> c
+%c -> cc
+&cc -> kc
+&kc -> rx
+",
+        );
+        assert_eq!(part2(&input).unwrap(), 4);
+    }
+
+    #[test]
+    fn counters_that_fire_mid_period_are_errors() {
+        // cb's circuit repeats every four presses but it also fires on presses 1 and 2.
+        let input = parse(
+            "broadcaster -> b
+%b -> c, cb
+%c -> cb
+&cb -> kc
+&kc -> rx
+",
+        );
+        assert!(part2(&input).is_err());
+    }
 }
@@ -1,10 +1,10 @@
 use itertools::Itertools;
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
 
 use aoc_runner_derive::{aoc, aoc_generator};
 #[aoc_generator(day3)]
-fn parse(input: &str) -> Vec<String> {
-    input.lines().map(String::from).collect::<Vec<_>>()
+fn parse(input: &str) -> Schematic {
+    Schematic::new(input)
 }
 
 fn is_symbol(c: char) -> bool {
@@ -19,133 +19,173 @@ fn neighborhood(
         .cartesian_product(start_col.saturating_sub(1)..=end_col.saturating_add(1))
 }
 
-#[aoc(day3, part1)]
-fn part1(input: &[String]) -> i64 {
-    let symbol_locs = input
-        .iter()
-        .enumerate()
-        .flat_map(|(row, line)| {
-            line.chars()
-                .enumerate()
-                .flat_map(move |(col, chr)| is_symbol(chr).then_some((row, col)))
-        })
-        .collect::<HashSet<_>>();
-    input
-        .iter()
-        .enumerate()
-        .map(|(row, line)| {
-            let regions = line
-                .chars()
-                .enumerate()
-                .map(|(col, chr)| ((col, col), vec![chr]))
-                .coalesce(|prev, curr| {
-                    if prev.1.iter().all(|c| c.is_numeric())
-                        && curr.1.iter().all(|c| c.is_numeric())
-                    {
-                        Ok((
-                            (prev.0 .0, curr.0 .1),
-                            prev.1.into_iter().chain(curr.1).collect(),
-                        ))
-                    } else {
-                        Err((prev, curr))
-                    }
-                })
-                .collect::<Vec<_>>();
-            let mut sum = 0;
-            for ((start, end), chars) in regions {
-                if !chars.iter().all(|c| c.is_numeric()) {
-                    continue;
-                }
-                let num = chars
-                    .into_iter()
-                    .collect::<String>()
-                    .parse::<i64>()
-                    .unwrap();
-                if neighborhood(row, (start, end))
-                    .into_iter()
-                    .any(|loc| symbol_locs.contains(&loc))
-                {
-                    sum += num;
-                }
-            }
-            sum
-        })
-        .sum()
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Number {
+    pub row: usize,
+    pub start_col: usize,
+    pub end_col: usize,
+    pub num: i64,
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
-struct Number {
-    row: usize,
-    start_col: usize,
-    end_col: usize,
-    num: i64,
+pub struct Symbol {
+    pub row: usize,
+    pub col: usize,
+    pub kind: char,
 }
 
-#[aoc(day3, part2)]
-fn part2(input: &[String]) -> i64 {
-    let number_regions: HashMap<(usize, usize), Number> = input
-        .iter()
-        .enumerate()
-        .flat_map(|(row, line)| {
-            line.chars()
-                .enumerate()
-                .map(|(col, chr)| ((col, col), vec![chr]))
-                .coalesce(|prev, curr| {
-                    if prev.1.iter().all(|c| c.is_numeric())
-                        && curr.1.iter().all(|c| c.is_numeric())
-                    {
-                        Ok((
-                            (prev.0 .0, curr.0 .1),
-                            prev.1.into_iter().chain(curr.1).collect(),
-                        ))
-                    } else {
-                        Err((prev, curr))
-                    }
-                })
-                .flat_map(move |((start_col, end_col), chars)| {
-                    chars.iter().all(|c| c.is_numeric()).then(move || Number {
+// Numbers and symbols are extracted once; `number_symbols[i]` and `symbol_numbers[j]` are the
+// two sides of the bipartite adjacency graph, holding indices into the other entity list.
+#[derive(Clone, Debug)]
+pub struct Schematic {
+    pub numbers: Vec<Number>,
+    pub symbols: Vec<Symbol>,
+    number_symbols: Vec<Vec<usize>>,
+    symbol_numbers: Vec<Vec<usize>>,
+}
+
+impl Schematic {
+    pub fn new(input: &str) -> Self {
+        let mut numbers = Vec::new();
+        let mut symbols = Vec::new();
+        for (row, line) in input.lines().enumerate() {
+            for (is_digit, group) in &line.chars().enumerate().group_by(|(_, c)| c.is_numeric()) {
+                let group = group.collect_vec();
+                if is_digit {
+                    numbers.push(Number {
                         row,
-                        start_col,
-                        end_col,
-                        num: chars
-                            .into_iter()
+                        start_col: group[0].0,
+                        end_col: group[group.len() - 1].0,
+                        num: group
+                            .iter()
+                            .map(|(_, c)| c)
                             .collect::<String>()
                             .parse::<i64>()
                             .unwrap(),
-                    })
-                })
-        })
-        .flat_map(
-            |me @ Number {
-                 row,
-                 start_col,
-                 end_col,
-                 num: _,
-             }| {
-                std::iter::once(row)
-                    .cartesian_product(start_col..=end_col)
-                    .map(move |k| (k, me))
-            },
-        )
-        .collect::<HashMap<_, _>>();
+                    });
+                } else {
+                    symbols.extend(
+                        group
+                            .into_iter()
+                            .filter(|(_, c)| is_symbol(*c))
+                            .map(|(col, kind)| Symbol { row, col, kind }),
+                    );
+                }
+            }
+        }
 
-    input
-        .iter()
-        .enumerate()
-        .flat_map(|(row, line)| {
-            let number_regions = &number_regions;
-            line.chars().enumerate().flat_map(move |(col, chr)| {
-                if chr != '*' {
-                    return None;
+        let symbol_locs = symbols
+            .iter()
+            .enumerate()
+            .map(|(i, s)| ((s.row, s.col), i))
+            .collect::<HashMap<_, _>>();
+        let mut number_symbols = vec![Vec::new(); numbers.len()];
+        let mut symbol_numbers = vec![Vec::new(); symbols.len()];
+        for (i, n) in numbers.iter().enumerate() {
+            for loc in neighborhood(n.row, (n.start_col, n.end_col)) {
+                if let Some(&j) = symbol_locs.get(&loc) {
+                    number_symbols[i].push(j);
+                    symbol_numbers[j].push(i);
                 }
-                let nums = neighborhood(row, (col, col))
-                    .into_iter()
-                    .flat_map(|k| number_regions.get(&k))
-                    .unique()
-                    .map(|Number { num, .. }| num)
-                    .collect::<Vec<_>>();
-                (nums.len() == 2).then(move || nums.into_iter().product::<i64>())
-            })
-        })
+            }
+        }
+
+        Schematic {
+            numbers,
+            symbols,
+            number_symbols,
+            symbol_numbers,
+        }
+    }
+
+    pub fn symbols_adjacent_to(&self, number: usize) -> impl Iterator<Item = &Symbol> {
+        self.number_symbols[number]
+            .iter()
+            .map(|&j| &self.symbols[j])
+    }
+
+    pub fn numbers_adjacent_to(&self, symbol: usize) -> impl Iterator<Item = &Number> {
+        self.symbol_numbers[symbol]
+            .iter()
+            .map(|&i| &self.numbers[i])
+    }
+
+    pub fn part_numbers(&self) -> impl Iterator<Item = &Number> {
+        self.numbers_where(|neighbors| !neighbors.is_empty())
+    }
+
+    pub fn isolated_numbers(&self) -> impl Iterator<Item = &Number> {
+        self.numbers_where(|neighbors| neighbors.is_empty())
+    }
+
+    pub fn numbers_adjacent_to_kind(&self, kind: char) -> impl Iterator<Item = &Number> {
+        self.numbers_where(move |neighbors| neighbors.iter().any(|s| s.kind == kind))
+    }
+
+    pub fn numbers_where<'a>(
+        &'a self,
+        pred: impl Fn(&[&Symbol]) -> bool + 'a,
+    ) -> impl Iterator<Item = &'a Number> {
+        self.numbers
+            .iter()
+            .enumerate()
+            .filter_map(move |(i, n)| pred(&self.symbols_adjacent_to(i).collect_vec()).then_some(n))
+    }
+
+    // Symbols of `kind` touching exactly `parts` numbers, along with those numbers.
+    pub fn gears(&self, kind: char, parts: usize) -> impl Iterator<Item = (&Symbol, Vec<&Number>)> {
+        self.symbols
+            .iter()
+            .enumerate()
+            .filter(move |(j, s)| s.kind == kind && self.symbol_numbers[*j].len() == parts)
+            .map(|(j, s)| (s, self.numbers_adjacent_to(j).collect()))
+    }
+}
+
+#[aoc(day3, part1)]
+fn part1(input: &Schematic) -> i64 {
+    input.part_numbers().map(|n| n.num).sum()
+}
+
+#[aoc(day3, part2)]
+fn part2(input: &Schematic) -> i64 {
+    input
+        .gears('*', 2)
+        .map(|(_, nums)| nums.iter().map(|n| n.num).product::<i64>())
         .sum()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sample() {
+        let schematic = parse(
+            "467..114..
+...*......
+..35..633.
+......#...
+617*......
+.....+.58.
+..592.....
+......755.
+...$.*....
+.664.598..
+",
+        );
+        assert_eq!(part1(&schematic), 4361);
+        assert_eq!(part2(&schematic), 467835);
+        assert_eq!(
+            schematic.isolated_numbers().map(|n| n.num).collect_vec(),
+            [114, 58]
+        );
+        assert_eq!(
+            schematic
+                .numbers_adjacent_to_kind('$')
+                .map(|n| n.num)
+                .collect_vec(),
+            [664]
+        );
+    }
+}
@@ -1,58 +1,160 @@
+use anyhow::{Context, Result};
 use aoc_runner_derive::{aoc, aoc_generator};
+use num::{BigUint, One, Zero};
 use std::collections::HashSet;
 
 #[derive(Clone, Debug)]
-struct Card {
-    winning: Vec<i64>,
-    have: Vec<i64>,
+pub struct Card {
+    pub id: usize,
+    pub winning: Vec<i64>,
+    pub have: Vec<i64>,
 }
 
-#[aoc_generator(day4)]
-fn parse(input: &str) -> Vec<Card> {
-    input
-        .lines()
-        .map(|line| {
-            let (_, nums) = line.split_once(": ").unwrap();
-            let (winning, have) = nums.split_once(" | ").unwrap();
-            let process = |s: &str| {
-                s.split_whitespace()
-                    .map(|n| n.parse::<i64>().unwrap())
-                    .collect::<Vec<_>>()
-            };
-            Card {
-                winning: process(winning),
-                have: process(have),
+impl Card {
+    pub fn matches(&self) -> usize {
+        let winning = self.winning.iter().collect::<HashSet<_>>();
+        self.have.iter().filter(|n| winning.contains(n)).count()
+    }
+}
+
+// The puzzle's scoring curve: 1 point for the first match, doubled for each one after. Big
+// integers, since a card with 65 or more matches is already past u64.
+pub fn doubling(matches: usize) -> BigUint {
+    match matches {
+        0 => BigUint::zero(),
+        n => BigUint::one() << (n - 1),
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Scratchcards {
+    pub cards: Vec<Card>,
+}
+
+impl Scratchcards {
+    pub fn parse(input: &str) -> Result<Self> {
+        let cards = input
+            .lines()
+            .map(|line| {
+                let (prefix, nums) = line.split_once(": ").context(line.to_string())?;
+                let id = prefix
+                    .strip_prefix("Card")
+                    .context(line.to_string())?
+                    .trim()
+                    .parse::<usize>()
+                    .context(line.to_string())?;
+                let (winning, have) = nums.split_once(" | ").context(line.to_string())?;
+                let process = |s: &str| {
+                    s.split_whitespace()
+                        .map(|n| n.parse::<i64>().context(line.to_string()))
+                        .collect::<Result<Vec<_>>>()
+                };
+                Ok(Card {
+                    id,
+                    winning: process(winning)?,
+                    have: process(have)?,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Scratchcards { cards })
+    }
+
+    pub fn points(&self, curve: impl Fn(usize) -> BigUint) -> BigUint {
+        self.cards.iter().map(|card| curve(card.matches())).sum()
+    }
+
+    // How many copies of each card end up being held, original included. Wins that would reach
+    // past the last card are dropped rather than wrapping or panicking. Counts can double with
+    // every card, so they are big integers too.
+    pub fn copy_counts(&self) -> Vec<BigUint> {
+        let mut counts = vec![BigUint::one(); self.cards.len()];
+        for (i, card) in self.cards.iter().enumerate() {
+            let last = (i + card.matches()).min(self.cards.len() - 1);
+            let (done, rest) = counts.split_at_mut(i + 1);
+            for count in rest.iter_mut().take(last - i) {
+                *count += &done[i];
             }
-        })
-        .collect::<Vec<_>>()
+        }
+        counts
+    }
+
+    pub fn total_cards(&self) -> BigUint {
+        self.copy_counts().into_iter().sum()
+    }
+}
+
+#[aoc_generator(day4)]
+fn parse(input: &str) -> Result<Scratchcards> {
+    Scratchcards::parse(input)
 }
 
 #[aoc(day4, part1)]
-fn part1(input: &[Card]) -> i64 {
-    input
-        .into_iter()
-        .map(|Card { winning, have }| {
-            let winning = winning.iter().collect::<HashSet<_>>();
-            let count = have.iter().filter(|n| winning.contains(*n)).count();
-            if count >= 1 {
-                (2 as i64).pow(u32::try_from(count).unwrap() - 1)
-            } else {
-                0
-            }
-        })
-        .sum()
+fn part1(input: &Scratchcards) -> BigUint {
+    input.points(doubling)
 }
 
 #[aoc(day4, part2)]
-fn part2(input: &[Card]) -> i64 {
-    let mut scores = vec![1i64; input.len()];
+fn part2(input: &Scratchcards) -> BigUint {
+    input.total_cards()
+}
 
-    for (i, Card { winning, have }) in input.into_iter().rev().enumerate() {
-        let winning = winning.iter().collect::<HashSet<_>>();
-        let count = have.iter().filter(|n| winning.contains(*n)).count();
-        let score: i64 = scores[i - count..i].iter().sum();
-        scores[i] += score;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn big(ns: &[u32]) -> Vec<BigUint> {
+        ns.iter().copied().map(BigUint::from).collect()
+    }
+
+    #[test]
+    fn sample() {
+        let cards = parse(
+            "Card 1: 41 48 83 86 17 | 83 86  6 31 17  9 48 53
+Card 2: 13 32 20 16 61 | 61 30 68 82 17 32 24 19
+Card 3:  1 21 53 59 44 | 69 82 63 72 16 21 14  1
+Card 4: 41 92 73 84 69 | 59 84 76 51 58  5 54 83
+Card 5: 87 83 26 28 32 | 88 30 70 12 93 22 82 36
+Card 6: 31 18 13 56 72 | 74 77 10 23 35 67 36 11
+",
+        )
+        .unwrap();
+        assert_eq!(part1(&cards), BigUint::from(13u32));
+        assert_eq!(cards.copy_counts(), big(&[1, 2, 4, 8, 14, 1]));
+        assert_eq!(part2(&cards), BigUint::from(30u32));
+        assert_eq!(cards.points(BigUint::from), BigUint::from(4u32 + 2 + 2 + 1));
     }
 
-    scores.iter().sum()
+    #[test]
+    fn cascade_clamps_at_end_of_table() {
+        let cards = parse("Card 1: 1 2 | 1 2\nCard 2: 1 2 | 1 2\n").unwrap();
+        assert_eq!(cards.copy_counts(), big(&[1, 2]));
+    }
+
+    #[test]
+    fn huge_match_counts_are_exact() {
+        assert_eq!(doubling(64), BigUint::from(1u64 << 63));
+        assert_eq!(doubling(65), BigUint::from(1u128 << 64));
+        let numbers = (1..=70)
+            .map(|n| n.to_string())
+            .collect::<Vec<_>>()
+            .join(" ");
+        let cards = parse(&format!(
+            "Card 1: {numbers} | {numbers}\nCard 2: {numbers} | {numbers}\n"
+        ))
+        .unwrap();
+        assert_eq!(part1(&cards), BigUint::from(2u128 << 69));
+
+        // Every card wins a copy of every later card, so the counts double each time.
+        let numbers = (1..=100)
+            .map(|n| n.to_string())
+            .collect::<Vec<_>>()
+            .join(" ");
+        let cards = parse(
+            &(1..=100)
+                .map(|id| format!("Card {id}: {numbers} | {numbers}\n"))
+                .collect::<String>(),
+        )
+        .unwrap();
+        assert_eq!(part2(&cards), (BigUint::one() << 100) - 1u32);
+    }
 }
@@ -1,19 +1,16 @@
-use std::{
-    collections::{BTreeMap, VecDeque},
-    ops::Bound,
-};
-
 use aoc_runner_derive::{aoc, aoc_generator};
 use itertools::Itertools;
 
+use crate::intervals::{Interval, IntervalSet};
+
 #[derive(Clone, Debug)]
-struct Input {
+pub struct Input {
     seeds: Vec<i64>,
     maps: Vec<Map>,
 }
 
 #[derive(Clone, Debug)]
-struct Map {
+pub struct Map {
     #[allow(dead_code)]
     left: String,
     #[allow(dead_code)]
@@ -28,53 +25,168 @@ struct Range {
     len: i64,
 }
 
-impl Range {
-    fn convert(&self, seed: i64) -> Option<i64> {
-        let Self {
-            src_start,
-            dest_start,
-            len,
-        } = *self;
-        (src_start..src_start + len)
-            .contains(&seed)
-            .then_some(seed - src_start + dest_start)
-    }
+// A total map on i64 that shifts each piece of its domain by a constant offset. `segments` holds
+// (start, offset) pairs sorted by start, each covering [start, next start); the first starts at
+// i64::MIN and the last runs to i64::MAX, so anything outside the almanac ranges maps to itself.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IntervalMap {
+    segments: Vec<(i64, i64)>,
 }
 
-#[derive(Clone, Debug, Copy, PartialEq, Eq, Hash)]
-struct RangeBounded {
-    start: Option<i64>,
-    end: Option<i64>,
-    displace: i64,
+impl IntervalMap {
+    pub fn identity() -> Self {
+        IntervalMap {
+            segments: vec![(i64::MIN, 0)],
+        }
+    }
+
+    fn from_pieces(pieces: impl IntoIterator<Item = (Interval, i64)>) -> Self {
+        let mut segments: Vec<(i64, i64)> = Vec::new();
+        for (interval, offset) in pieces.into_iter().sorted() {
+            if interval.is_empty() {
+                continue;
+            }
+            match segments.last() {
+                Some(&(_, last_offset)) if last_offset == offset => {}
+                _ => segments.push((interval.start, offset)),
+            }
+        }
+        IntervalMap { segments }
+    }
+
+    pub fn pieces(&self) -> impl Iterator<Item = (Interval, i64)> + '_ {
+        self.segments
+            .iter()
+            .enumerate()
+            .map(|(i, &(start, offset))| {
+                let end = self.segments.get(i + 1).map_or(i64::MAX, |(s, _)| *s);
+                (Interval::new(start, end), offset)
+            })
+    }
+
+    fn pieces_overlapping(&self, range: Interval) -> impl Iterator<Item = (Interval, i64)> + '_ {
+        let first = self.segments.partition_point(|(s, _)| *s <= range.start) - 1;
+        self.pieces()
+            .skip(first)
+            .take_while(move |(piece, _)| piece.start < range.end)
+            .filter_map(move |(piece, offset)| Some((piece.intersect(&range)?, offset)))
+    }
+
+    // Overwrites `range` with `offset`, leaving the rest of the map untouched.
+    pub fn with_range(&self, range: Interval, offset: i64) -> Self {
+        let outside = IntervalSet::from(range).complement();
+        Self::from_pieces(
+            self.pieces()
+                .flat_map(|(piece, d)| {
+                    outside
+                        .intersection(&piece.into())
+                        .intervals()
+                        .iter()
+                        .map(|i| (*i, d))
+                        .collect_vec()
+                })
+                .chain(std::iter::once((range, offset))),
+        )
+    }
+
+    pub fn apply(&self, x: i64) -> i64 {
+        let i = self.segments.partition_point(|(s, _)| *s <= x) - 1;
+        x + self.segments[i].1
+    }
+
+    // The map equivalent to applying `self` and then `then`.
+    pub fn compose(&self, then: &IntervalMap) -> IntervalMap {
+        Self::from_pieces(self.pieces().flat_map(|(piece, d)| {
+            then.pieces_overlapping(piece.shift(d))
+                .map(move |(image, d2)| (image.shift(-d), d + d2))
+                .collect_vec()
+        }))
+    }
+
+    // Only bijections have an inverse: the shifted pieces must tile the whole line exactly.
+    pub fn invert(&self) -> anyhow::Result<IntervalMap> {
+        let images = self
+            .pieces()
+            .map(|(piece, d)| (piece.shift(d), -d))
+            .sorted()
+            .collect_vec();
+        let mut expected = i64::MIN;
+        for (image, _) in &images {
+            anyhow::ensure!(
+                image.start == expected,
+                "map is not invertible: {} is covered {}",
+                expected.min(image.start),
+                if image.start < expected {
+                    "twice"
+                } else {
+                    "never"
+                }
+            );
+            expected = image.end;
+        }
+        anyhow::ensure!(
+            expected == i64::MAX,
+            "map is not invertible: {expected} is never covered"
+        );
+        Ok(Self::from_pieces(images))
+    }
+
+    // Every value reached from `domain`.
+    pub fn image(&self, domain: &IntervalSet) -> IntervalSet {
+        domain
+            .intervals()
+            .iter()
+            .flat_map(|range| {
+                self.pieces_overlapping(*range)
+                    .map(|(piece, d)| piece.shift(d))
+            })
+            .collect()
+    }
+
+    // Every value that lands in `target`, even when the map is not invertible.
+    pub fn preimage(&self, target: &IntervalSet) -> IntervalSet {
+        self.pieces()
+            .flat_map(|(piece, d)| {
+                target
+                    .intersection(&piece.shift(d).into())
+                    .intervals()
+                    .iter()
+                    .map(|i| i.shift(-d))
+                    .collect_vec()
+            })
+            .collect()
+    }
+
+    pub fn min_over(&self, domain: &IntervalSet) -> Option<i64> {
+        self.image(domain).min()
+    }
 }
 
-impl RangeBounded {
-    fn convert_range(&self, seed_start: i64, seed_len: i64) -> Option<(i64, i64)> {
-        let Self {
-            start,
-            end,
-            displace,
-        } = *self;
-        let source_seed_start = seed_start.max(start.unwrap_or(seed_start));
-        let source_seed_end = (seed_start + seed_len).min(end.unwrap_or(seed_start + seed_len));
-        let converted_seed_len = source_seed_end - source_seed_start;
-        (converted_seed_len > 0).then_some((source_seed_start + displace, converted_seed_len))
+impl Map {
+    pub fn to_interval_map(&self) -> IntervalMap {
+        self.ranges.iter().fold(
+            IntervalMap::identity(),
+            |map,
+             Range {
+                 src_start,
+                 dest_start,
+                 len,
+             }| {
+                map.with_range(
+                    Interval::new(*src_start, src_start + len),
+                    dest_start - src_start,
+                )
+            },
+        )
     }
 }
 
-impl From<Range> for RangeBounded {
-    fn from(
-        Range {
-            src_start,
-            dest_start,
-            len,
-        }: Range,
-    ) -> Self {
-        RangeBounded {
-            start: Some(src_start),
-            end: Some(src_start + len),
-            displace: dest_start - src_start,
-        }
+impl Input {
+    // The whole seed-to-location chain as a single map.
+    pub fn chain(&self) -> IntervalMap {
+        self.maps.iter().fold(IntervalMap::identity(), |acc, map| {
+            acc.compose(&map.to_interval_map())
+        })
     }
 }
 
@@ -125,120 +237,90 @@ fn parse(input: &str) -> Input {
 
 #[aoc(day5, part1)]
 fn part1(input: &Input) -> i64 {
-    let Input { seeds, maps } = input;
-
-    let mut seeds = seeds.clone();
-    for map in maps.iter().map(
-        |Map {
-             left: _,
-             right: _,
-             ranges,
-         }| {
-            ranges
-                .into_iter()
-                .map(
-                    |range @ Range {
-                         src_start,
-                         dest_start: _,
-                         len: _,
-                     }| { (*src_start, *range) },
-                )
-                .collect::<BTreeMap<_, _>>()
-        },
-    ) {
-        seeds = seeds
-            .into_iter()
-            .map(|seed| {
-                let Some((_, candidate)) = map.range(..=seed).rev().next() else {
-                    return seed;
-                };
-                candidate.convert(seed).unwrap_or(seed)
-            })
-            .collect::<Vec<_>>();
-    }
-
-    seeds.into_iter().min().unwrap()
+    let chain = input.chain();
+    input
+        .seeds
+        .iter()
+        .map(|&seed| chain.apply(seed))
+        .min()
+        .unwrap()
 }
 
 #[aoc(day5, part2)]
 fn part2(input: &Input) -> i64 {
-    let Input { seeds, maps } = input;
+    let seeds = input
+        .seeds
+        .iter()
+        .tuples()
+        .map(|(&start, &len)| Interval::new(start, start + len))
+        .collect();
+    input.chain().min_over(&seeds).unwrap()
+}
 
-    let mut seeds = seeds
-        .clone()
-        .into_iter()
-        .tuples::<(_, _)>()
-        .collect::<Vec<_>>();
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    for map in maps.iter().map(
-        |Map {
-             left: _,
-             right: _,
-             ranges,
-         }| {
-            ranges
-                .into_iter()
-                .map(
-                    |range @ Range {
-                         src_start,
-                         dest_start: _,
-                         len: _,
-                     }| { (*src_start, *range) },
-                )
-                .collect::<BTreeMap<_, _>>()
-        },
-    ) {
-        seeds = seeds
-            .into_iter()
-            .flat_map(|(seed_start, seed_len)| {
-                let preceding = map
-                    .range(..seed_start)
-                    .rev()
-                    .next()
-                    .map(|(start, _)| Bound::Included(*start))
-                    .unwrap_or(Bound::Unbounded);
-                let succeeding = map
-                    .range(seed_start + seed_len..)
-                    .next()
-                    .map(|(start, _)| Bound::Excluded(*start))
-                    .unwrap_or(Bound::Unbounded);
-
-                let mut ranges_to_consider = map
-                    .range((preceding, succeeding))
-                    .map(|(_, &range)| RangeBounded::from(range))
-                    .collect::<VecDeque<_>>();
-
-                if ranges_to_consider.is_empty() {
-                    ranges_to_consider.push_back(RangeBounded {
-                        start: None,
-                        end: None,
-                        displace: 0,
-                    });
-                }
+    const SAMPLE: &str = "seeds: 79 14 55 13
 
-                if let Some(n) = ranges_to_consider.front().unwrap().start {
-                    ranges_to_consider.push_front(RangeBounded {
-                        start: None,
-                        end: Some(n),
-                        displace: 0,
-                    });
-                }
+seed-to-soil map:
+50 98 2
+52 50 48
 
-                if let Some(n) = ranges_to_consider.back().unwrap().end {
-                    ranges_to_consider.push_back(RangeBounded {
-                        start: Some(n),
-                        end: None,
-                        displace: 0,
-                    });
-                }
+soil-to-fertilizer map:
+0 15 37
+37 52 2
+39 0 15
 
-                ranges_to_consider
-                    .into_iter()
-                    .filter_map(|range| range.convert_range(seed_start, seed_len))
-                    .collect::<Vec<_>>()
-            })
-            .collect::<Vec<_>>();
+fertilizer-to-water map:
+49 53 8
+0 11 42
+42 0 7
+57 7 4
+
+water-to-light map:
+88 18 7
+18 25 70
+
+light-to-temperature map:
+45 77 23
+81 45 19
+68 64 13
+
+temperature-to-humidity map:
+0 69 1
+1 0 69
+
+humidity-to-location map:
+60 56 37
+56 93 4
+";
+
+    #[test]
+    fn sample() {
+        let input = parse(SAMPLE);
+        assert_eq!(part1(&input), 35);
+        assert_eq!(part2(&input), 46);
     }
 
-    seeds.into_iter().map(|(x, _)| x).min().unwrap()
+    #[test]
+    fn compose_and_invert() {
+        let input = parse(SAMPLE);
+        let chain = input.chain();
+        for seed in 0..120 {
+            let stepwise = input
+                .maps
+                .iter()
+                .fold(seed, |x, map| map.to_interval_map().apply(x));
+            assert_eq!(chain.apply(seed), stepwise);
+        }
+
+        let inverse = chain.invert().unwrap();
+        assert_eq!(inverse.apply(46), 82);
+        assert_eq!(chain.compose(&inverse), IntervalMap::identity());
+        assert_eq!(
+            chain.preimage(&Interval::new(46, 47).into()),
+            Interval::new(82, 83).into()
+        );
+    }
 }
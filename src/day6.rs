@@ -1,15 +1,31 @@
 use aoc_runner_derive::{aoc, aoc_generator};
 use itertools::Itertools;
+use num::{BigInt, One, Zero};
 
-// tc - c^2 - record
-fn quad_roots(t: f64, record: f64) -> [f64; 2] {
-    let a = -1f64;
-    let b = t;
-    let c = -record;
-
-    let p = (-b + f64::sqrt(b.powi(2) - 4f64 * a * c)) / (2f64 * a);
-    let q = (-b - f64::sqrt(b.powi(2) - 4f64 * a * c)) / (2f64 * a);
-    [p.min(q), p.max(q)]
+// Holding the button for c ms wins when c(t - c) > record. The real roots of that quadratic are
+// (t ± sqrt(t^2 - 4 record)) / 2; an integer square root gets within one of the lowest winning
+// hold time, and the exact comparison settles the rest, so exact-integer roots (ties) are
+// excluded and nothing depends on float precision.
+pub fn ways_to_win(time: &BigInt, record: &BigInt) -> BigInt {
+    let wins = |c: &BigInt| c * (time - c) > *record;
+    let discriminant: BigInt = time * time - record * 4;
+    if discriminant < BigInt::zero() {
+        return BigInt::zero();
+    }
+    let root = discriminant.sqrt();
+    let mut lo: BigInt = ((time - root) / 2u32).max(BigInt::zero());
+    while lo > BigInt::zero() && wins(&(&lo - 1u32)) {
+        lo -= 1u32;
+    }
+    while &lo * 2u32 <= *time && !wins(&lo) {
+        lo += 1u32;
+    }
+    let hi = time - &lo;
+    if hi < lo {
+        BigInt::zero()
+    } else {
+        hi - lo + 1u32
+    }
 }
 
 #[aoc_generator(day6)]
@@ -25,32 +41,66 @@ fn parse(input: &str) -> Vec<(i64, i64)> {
 }
 
 #[aoc(day6, part1)]
-fn part1(input: &[(i64, i64)]) -> i64 {
+fn part1(input: &[(i64, i64)]) -> BigInt {
     input
-        .into_iter()
-        .map(|(time, record)| {
-            let [floor, ceil] = quad_roots(time.clone() as f64, record.clone() as f64);
-            let floor = floor.floor() as i64 + 1;
-            let ceil = ceil.ceil() as i64 - 1;
-            ceil - floor + 1
-        })
-        .product()
+        .iter()
+        .map(|(time, record)| ways_to_win(&BigInt::from(*time), &BigInt::from(*record)))
+        .fold(BigInt::one(), |acc, n| acc * n)
 }
 
+// The kerning-corrected race can be longer than any fixed-width integer, so the digits are
+// joined as strings and parsed straight into a BigInt.
 #[aoc(day6, part2)]
-fn part2(input: &[(i64, i64)]) -> i64 {
+fn part2(input: &[(i64, i64)]) -> BigInt {
     let time = input
-        .into_iter()
+        .iter()
         .map(|(t, _)| t.to_string())
         .join("")
-        .parse::<i64>()
+        .parse::<BigInt>()
         .unwrap();
-    let distance = input
-        .into_iter()
+    let record = input
+        .iter()
         .map(|(_, d)| d.to_string())
         .join("")
-        .parse::<i64>()
+        .parse::<BigInt>()
         .unwrap();
-    let input = [(time, distance)];
-    part1(&input)
+    ways_to_win(&time, &record)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ways_to_win_brute(time: i64, record: i64) -> i64 {
+        (0..=time).filter(|c| c * (time - c) > record).count() as i64
+    }
+
+    #[test]
+    fn sample() {
+        let input = parse("Time:      7  15   30\nDistance:  9  40  200\n");
+        assert_eq!(part1(&input), BigInt::from(288));
+        assert_eq!(part2(&input), BigInt::from(71503));
+    }
+
+    #[test]
+    fn matches_brute_force() {
+        for time in 0..80 {
+            for record in -3..=(time * time / 4 + 2) {
+                assert_eq!(
+                    ways_to_win(&BigInt::from(time), &BigInt::from(record)),
+                    BigInt::from(ways_to_win_brute(time, record)),
+                    "time {time}, record {record}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn exact_roots_beyond_f64_precision() {
+        // Roots at exactly 10^20 and 3 * 10^20: both ties lose.
+        let a = BigInt::from(10).pow(20);
+        let b = &a * 3;
+        let ways = ways_to_win(&(&a + &b), &(&a * &b));
+        assert_eq!(ways, &b - &a - 1);
+    }
 }
@@ -1,163 +1,261 @@
-use std::cmp::Reverse;
+use std::{cmp::Ordering, fmt};
 
+use anyhow::{bail, ensure, Context, Result};
 use aoc_runner_derive::{aoc, aoc_generator};
 use itertools::Itertools;
 
-#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
-enum Card {
-    Ace,
-    King,
-    Queen,
-    Jack,
-    Ten,
-    Num(Reverse<i32>),
+// The sizes of the groups of equal cards, largest first. Comparing these lexicographically gives
+// the usual ordering (five of a kind > four of a kind > full house > ...) and extends it to
+// hands of any length.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct HandType {
+    groups: Vec<usize>,
 }
 
-impl From<char> for Card {
-    fn from(value: char) -> Self {
-        use Card::*;
-        match value {
-            'A' => Ace,
-            'K' => King,
-            'Q' => Queen,
-            'J' => Jack,
-            'T' => Ten,
-            n => Num(Reverse(n.to_string().parse().unwrap())),
+impl HandType {
+    pub fn groups(&self) -> &[usize] {
+        &self.groups
+    }
+}
+
+impl fmt::Display for HandType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.groups.as_slice() {
+            [5] => write!(f, "five of a kind"),
+            [4, 1] => write!(f, "four of a kind"),
+            [3, 2] => write!(f, "full house"),
+            [3, 1, 1] => write!(f, "three of a kind"),
+            [2, 2, 1] => write!(f, "two pair"),
+            [2, 1, 1, 1] => write!(f, "one pair"),
+            [1, 1, 1, 1, 1] => write!(f, "high card"),
+            groups => write!(f, "{}", groups.iter().join("+")),
         }
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
-enum Card2 {
-    Ace,
-    King,
-    Queen,
-    Ten,
-    Num(Reverse<i32>),
-    Joker,
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct Hand {
+    pub cards: Vec<char>,
 }
 
-impl From<char> for Card2 {
-    fn from(value: char) -> Self {
-        use Card2::*;
-        match value {
-            'A' => Ace,
-            'K' => King,
-            'Q' => Queen,
-            'J' => Joker,
-            'T' => Ten,
-            n => Num(Reverse(n.to_string().parse().unwrap())),
-        }
+impl fmt::Display for Hand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.cards.iter().collect::<String>())
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
-enum HandType {
-    FiveK,
-    FourK,
-    FullHouse,
-    ThreeK,
-    TwoPair,
-    OnePair,
-    HighCard,
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct RankedHand {
+    pub hand: Hand,
+    pub bid: i64,
+    pub ty: HandType,
+    pub effective: Hand,
+    // 1 is the weakest hand.
+    pub rank: usize,
+    pub winnings: i64,
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
-struct Hand {
-    cards: [Card; 5],
+#[derive(Clone, Debug)]
+pub struct RankingRules {
+    // Weakest first.
+    order: Vec<char>,
+    wild: Vec<char>,
+    hand_size: usize,
 }
 
-impl Hand {
-    fn ty(&self) -> HandType {
-        let Hand { cards } = self;
-        if cards.iter().all_equal() {
-            return HandType::FiveK;
-        }
-        let counts = cards.iter().copied().counts();
-        let counts_counts = counts.values().copied().counts();
-
-        if counts_counts.get(&4).is_some() {
-            return HandType::FourK;
+impl RankingRules {
+    pub fn new(order: &str, wild: &str, hand_size: usize) -> Result<Self> {
+        let order = order.chars().collect_vec();
+        ensure!(order.iter().all_unique(), "duplicate card in {order:?}");
+        let wild = wild.chars().collect_vec();
+        if let Some(c) = wild.iter().find(|c| !order.contains(c)) {
+            bail!("wild card {c:?} is not in the card order");
         }
+        Ok(RankingRules {
+            order,
+            wild,
+            hand_size,
+        })
+    }
 
-        if counts_counts.get(&3).is_some() {
-            if counts_counts.get(&2).is_some() {
-                return HandType::FullHouse;
-            } else {
-                return HandType::ThreeK;
-            }
-        }
+    pub fn standard() -> Self {
+        Self::new("23456789TJQKA", "", 5).unwrap()
+    }
 
-        match counts_counts.get(&2).copied().unwrap_or(0) {
-            2 => return HandType::TwoPair,
-            1 => return HandType::OnePair,
-            _ => return HandType::HighCard,
-        }
+    pub fn jokers() -> Self {
+        Self::new("J23456789TQKA", "J", 5).unwrap()
     }
-}
 
-#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
-struct Hand2 {
-    cards: [Card2; 5],
-}
+    pub fn strength(&self, card: char) -> Option<usize> {
+        self.order.iter().position(|c| *c == card)
+    }
 
-impl Hand2 {
-    fn ty(&self) -> HandType {
-        let Hand2 { cards } = self;
-        let mut counts = cards.iter().copied().counts();
-        let jokers = counts.remove(&Card2::Joker).unwrap_or(0);
-        let counts = counts;
+    pub fn is_wild(&self, card: char) -> bool {
+        self.wild.contains(&card)
+    }
 
-        if counts.len() == 0 {
-            return HandType::FiveK;
+    // Wild cards always do best joining the largest group, since that maximises the first
+    // (most significant) group size and leaves the rest unchanged.
+    pub fn classify(&self, hand: &Hand) -> HandType {
+        let wilds = hand.cards.iter().filter(|c| self.is_wild(**c)).count();
+        let mut groups = hand
+            .cards
+            .iter()
+            .filter(|c| !self.is_wild(**c))
+            .counts()
+            .into_values()
+            .sorted_by(|a, b| b.cmp(a))
+            .collect_vec();
+        match groups.first_mut() {
+            Some(largest) => *largest += wilds,
+            None if wilds > 0 => groups.push(wilds),
+            None => {}
         }
+        HandType { groups }
+    }
+
+    pub fn key(&self, hand: &Hand) -> (HandType, Vec<usize>) {
+        (
+            self.classify(hand),
+            hand.cards
+                .iter()
+                .map(|c| self.strength(*c).unwrap())
+                .collect(),
+        )
+    }
 
-        if counts.values().copied().max().unwrap() + jokers == 5 {
-            return HandType::FiveK;
+    pub fn parse_hand(&self, s: &str) -> Result<Hand> {
+        let cards = s.chars().collect_vec();
+        ensure!(
+            cards.len() == self.hand_size,
+            "hand {s:?} does not have {} cards",
+            self.hand_size
+        );
+        if let Some(c) = cards.iter().find(|c| self.strength(**c).is_none()) {
+            bail!("unknown card {c:?} in hand {s:?}");
         }
+        Ok(Hand { cards })
+    }
+
+    pub fn parse_bids(&self, input: &str) -> Result<Vec<(Hand, i64)>> {
+        input
+            .lines()
+            .map(|line| {
+                let (hand, bid) = line.split_once(' ').context(line.to_string())?;
+                Ok((
+                    self.parse_hand(hand)?,
+                    bid.parse::<i64>().context(line.to_string())?,
+                ))
+            })
+            .collect()
+    }
+
+    // Weakest first.
+    pub fn rank(&self, mut bids: Vec<(Hand, i64)>) -> Vec<(Hand, i64)> {
+        bids.sort_by_cached_key(|(hand, _)| self.key(hand));
+        bids
+    }
 
-        if counts.values().copied().max().unwrap() + jokers == 4 {
-            return HandType::FourK;
+    // Every wild card becomes the rank of the largest natural group (the strongest such rank on
+    // ties), or the strongest rank overall if the hand is all wilds.
+    pub fn effective_hand(&self, hand: &Hand) -> Hand {
+        let counts = hand.cards.iter().filter(|c| !self.is_wild(**c)).counts();
+        let target = counts
+            .into_iter()
+            .max_by_key(|(c, n)| (*n, self.strength(**c)))
+            .map_or(*self.order.last().unwrap(), |(c, _)| *c);
+        Hand {
+            cards: hand
+                .cards
+                .iter()
+                .map(|c| if self.is_wild(*c) { target } else { *c })
+                .collect(),
         }
+    }
 
-        let counts_counts = counts.values().copied().counts();
-
-        // At this point, we know that we have at most two jokers (because three
-        // would allow for a four-of-a-kind guaranteed).
-        if jokers == 2 {
-            // We must have all distinct non-jokers (or else we'd have a four-of-a-kind).
-            // Then the best we can do is three-of-a-kind.
-            return HandType::ThreeK;
-        } else if jokers == 1 {
-            // There must be no three-of-a-kind or we'd have made a four-of-a-kind.
-            // So we just need to check how many pairs we have (0, 1, 2).
-            match counts_counts.get(&2).copied().unwrap_or(0) {
-                2 => return HandType::FullHouse,
-                1 => return HandType::ThreeK,
-                0 | _ => return HandType::OnePair,
-            }
-        } else {
-            assert_eq!(jokers, 0);
+    // In rank order, weakest first.
+    pub fn explain(&self, bids: Vec<(Hand, i64)>) -> Vec<RankedHand> {
+        self.rank(bids)
+            .into_iter()
+            .enumerate()
+            .map(|(index, (hand, bid))| RankedHand {
+                ty: self.classify(&hand),
+                effective: self.effective_hand(&hand),
+                rank: index + 1,
+                winnings: i64::try_from(index + 1).unwrap() * bid,
+                hand,
+                bid,
+            })
+            .collect()
+    }
 
-            // Normal hand rules. To be lazy just copy-pasting above section.
-            if counts_counts.get(&4).is_some() {
-                return HandType::FourK;
-            }
+    pub fn total_winnings(&self, input: &str) -> Result<i64> {
+        Ok(self
+            .explain(self.parse_bids(input)?)
+            .iter()
+            .map(|ranked| ranked.winnings)
+            .sum())
+    }
 
-            if counts_counts.get(&3).is_some() {
-                if counts_counts.get(&2).is_some() {
-                    return HandType::FullHouse;
-                } else {
-                    return HandType::ThreeK;
-                }
+    // Walks the tie-break for two hands: hand type first, then card strengths left to right,
+    // stopping at the first step that decides it.
+    pub fn compare_report(&self, a: &Hand, b: &Hand) -> String {
+        let header = |h: &Hand| {
+            let effective = self.effective_hand(h);
+            if effective == *h {
+                h.to_string()
+            } else {
+                format!("{h} ({effective})")
             }
+        };
+        let mut rows = vec![[
+            "step".to_string(),
+            header(a),
+            header(b),
+            "result".to_string(),
+        ]];
+        let verdict = |ord: Ordering| match ord {
+            Ordering::Less => format!("{b} wins"),
+            Ordering::Greater => format!("{a} wins"),
+            Ordering::Equal => "tie".to_string(),
+        };
 
-            match counts_counts.get(&2).copied().unwrap_or(0) {
-                2 => return HandType::TwoPair,
-                1 => return HandType::OnePair,
-                _ => return HandType::HighCard,
+        let (ty_a, ty_b) = (self.classify(a), self.classify(b));
+        let mut ord = ty_a.cmp(&ty_b);
+        rows.push([
+            "type".to_string(),
+            ty_a.to_string(),
+            ty_b.to_string(),
+            verdict(ord),
+        ]);
+        for (i, (ca, cb)) in a.cards.iter().zip(&b.cards).enumerate() {
+            if ord.is_ne() {
+                break;
             }
+            let (sa, sb) = (self.strength(*ca), self.strength(*cb));
+            ord = sa.cmp(&sb);
+            rows.push([
+                format!("card {}", i + 1),
+                format!("{ca} ({})", sa.unwrap()),
+                format!("{cb} ({})", sb.unwrap()),
+                verdict(ord),
+            ]);
         }
+
+        let widths = (0..4)
+            .map(|col| rows.iter().map(|row| row[col].len()).max().unwrap())
+            .collect_vec();
+        rows.iter()
+            .map(|row| {
+                row.iter()
+                    .zip(&widths)
+                    .map(|(cell, width)| format!("{cell:width$}"))
+                    .join(" | ")
+                    .trim_end()
+                    .to_string()
+                    + "\n"
+            })
+            .collect()
     }
 }
 
@@ -166,70 +264,63 @@ fn parse(input: &str) -> String {
     input.to_string()
 }
 
-fn parse1(input: &str) -> Vec<(Hand, i64)> {
-    input
-        .lines()
-        .map(|line| {
-            let (hand, bid) = line.split_once(" ").unwrap();
-            let hand = hand.chars().map(Card::from).collect::<Vec<_>>();
-            let bid = bid.parse::<i64>().unwrap();
-            (
-                Hand {
-                    cards: hand.try_into().unwrap(),
-                },
-                bid,
-            )
-        })
-        .collect()
+#[aoc(day7, part1)]
+fn part1(input: &str) -> Result<i64> {
+    RankingRules::standard().total_winnings(input)
 }
 
-fn parse2(input: &str) -> Vec<(Hand2, i64)> {
-    input
-        .lines()
-        .map(|line| {
-            let (hand, bid) = line.split_once(" ").unwrap();
-            let hand = hand.chars().map(Card2::from).collect::<Vec<_>>();
-            let bid = bid.parse::<i64>().unwrap();
-            (
-                Hand2 {
-                    cards: hand.try_into().unwrap(),
-                },
-                bid,
-            )
-        })
-        .collect()
+#[aoc(day7, part2)]
+fn part2(input: &str) -> Result<i64> {
+    RankingRules::jokers().total_winnings(input)
 }
 
-#[aoc(day7, part1)]
-fn part1(input: &str) -> i64 {
-    let mut input = parse1(input);
-    input.sort_by(|(hand_a, _), (hand_b, _)| {
-        hand_a
-            .ty()
-            .cmp(&hand_b.ty())
-            .then(hand_a.cmp(hand_b))
-            .reverse()
-    });
-    input
-        .into_iter()
-        .enumerate()
-        .map(|(index, (_, rank))| i64::try_from(index + 1).unwrap() * rank)
-        .sum()
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-#[aoc(day7, part2)]
-fn part2(input: &str) -> i64 {
-    let mut input = parse2(input);
-    input.sort_by(|(hand_a, _), (hand_b, _)| {
-        hand_a
-            .ty()
-            .cmp(&hand_b.ty())
-            .then(hand_a.cmp(hand_b))
-            .reverse()
-    });
-    input
-        .into_iter()
-        .enumerate()
-        .map(|(index, (_, rank))| i64::try_from(index + 1).unwrap() * rank)
-        .sum()
+    const SAMPLE: &str = "32T3K 765
+T55J5 684
+KK677 28
+KTJJT 220
+QQQJA 483
+";
+
+    #[test]
+    fn sample() {
+        assert_eq!(part1(&parse(SAMPLE)).unwrap(), 6440);
+        assert_eq!(part2(&parse(SAMPLE)).unwrap(), 5905);
+    }
+
+    #[test]
+    fn multiple_wilds_and_long_hands() {
+        let rules = RankingRules::new("23456789TJQKA", "2J", 7).unwrap();
+        let classify = |s: &str| rules.classify(&rules.parse_hand(s).unwrap());
+        assert_eq!(classify("2JAAKKQ").groups(), [4, 2, 1]);
+        assert_eq!(classify("22JJ22J").groups(), [7]);
+        assert!(classify("AAAKKKQ") > classify("2JAKQT9"));
+        assert!(rules.parse_hand("AAAAA").is_err());
+    }
+
+    #[test]
+    fn explanation() {
+        let rules = RankingRules::jokers();
+        let ranked = rules.explain(rules.parse_bids(SAMPLE).unwrap());
+        let strongest = ranked.last().unwrap();
+        assert_eq!(strongest.hand.to_string(), "KTJJT");
+        assert_eq!(strongest.effective.to_string(), "KTTTT");
+        assert_eq!(strongest.ty.to_string(), "four of a kind");
+        assert_eq!((strongest.rank, strongest.winnings), (5, 1100));
+        for r in &ranked {
+            assert_eq!(RankingRules::standard().classify(&r.effective), r.ty);
+        }
+
+        let hand = |s| rules.parse_hand(s).unwrap();
+        assert_eq!(
+            rules.compare_report(&hand("QQQJA"), &hand("T55J5")),
+            "step   | QQQJA (QQQQA)  | T55J5 (T5555)  | result
+type   | four of a kind | four of a kind | tie
+card 1 | Q (10)         | T (9)          | QQQJA wins
+"
+        );
+    }
 }
@@ -1,123 +1,363 @@
-use std::collections::HashMap;
+use std::{
+    collections::{HashMap, HashSet},
+    iter::successors,
+};
 
+use anyhow::{bail, ensure, Context, Result};
 use aoc_runner_derive::{aoc, aoc_generator};
 use itertools::Itertools;
-use num::integer::lcm;
+use num::integer::{ExtendedGcd, Integer};
+use petgraph::graphmap::DiGraphMap;
+
+use crate::cycle::{Cycle, History};
 
 #[derive(Clone, Copy, PartialEq, PartialOrd, Eq, Ord, Hash, Debug)]
-enum RL {
+pub enum RL {
     R,
     L,
 }
 
 #[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Hash, Debug)]
-struct Line {
+pub struct Line {
     lhs: String,
     rhs: (String, String),
 }
 
-struct Input {
+pub struct Input {
     rls: Vec<RL>,
     lines: Vec<Line>,
 }
 
 #[aoc_generator(day8)]
-fn parse(input: &str) -> Input {
-    let (rls, lines) = input.trim().split_once("\n\n").unwrap();
+fn parse(input: &str) -> Result<Input> {
+    let (rls, lines) = input
+        .trim()
+        .split_once("\n\n")
+        .context("no blank line after the instructions")?;
     let rls = rls
         .chars()
         .map(|c| match c {
-            'R' => RL::R,
-            'L' => RL::L,
-            c => panic!("{c}"),
+            'R' => Ok(RL::R),
+            'L' => Ok(RL::L),
+            c => bail!("unknown instruction {c:?}"),
         })
-        .collect_vec();
+        .collect::<Result<_>>()?;
     let lines = lines
         .lines()
         .map(|line| {
-            let (lhs, rhs) = line.split_once(" = ").unwrap();
-            let (fst, snd) = rhs.split_once(", ").unwrap();
-            let fst = &fst[1..];
-            let snd = &snd[..snd.len() - 1];
-            Line {
+            let (lhs, rhs) = line.split_once(" = ").context(line.to_string())?;
+            let (fst, snd) = rhs
+                .strip_prefix('(')
+                .and_then(|rhs| rhs.strip_suffix(')'))
+                .and_then(|rhs| rhs.split_once(", "))
+                .context(line.to_string())?;
+            Ok(Line {
                 lhs: lhs.to_string(),
                 rhs: (fst.to_string(), snd.to_string()),
-            }
+            })
         })
-        .collect_vec();
-    Input { rls, lines }
+        .collect::<Result<_>>()?;
+    Ok(Input { rls, lines })
 }
 
-#[aoc(day8, part1)]
-fn part1(input: &Input) -> i64 {
-    let Input { rls, lines } = input;
-    let linesmap = lines
-        .into_iter()
-        .map(|Line { lhs, rhs }| (lhs.clone(), rhs.clone()))
-        .collect::<HashMap<_, _>>();
-    let mut rls = rls.iter().cycle();
-
-    let mut curr = "AAA";
-    let mut steps = 0i64;
-    while curr != "ZZZ" {
-        steps += 1;
-        let (l, r) = linesmap.get(curr).unwrap();
-        curr = match rls.next().unwrap() {
-            RL::R => r.as_str(),
-            RL::L => l.as_str(),
-        };
-    }
+pub type NodeId = usize;
 
-    steps
-}
+// A position in the walk: the current node and the index of the next instruction. The walk is
+// deterministic on these, so it either reaches a target or loops forever.
+pub type State = (NodeId, usize);
 
-struct CycleData {
-    seen_zs: HashMap<String, usize>,
+#[derive(Clone, Debug)]
+pub struct Network {
+    rls: Vec<RL>,
+    names: Vec<String>,
+    ids: HashMap<String, NodeId>,
+    next: Vec<(NodeId, NodeId)>,
 }
 
-#[aoc(day8, part2)]
-fn part2(input: &Input) -> usize {
-    let Input { rls, lines } = input;
-    let linesmap = lines
-        .into_iter()
-        .map(|Line { lhs, rhs }| (lhs.clone(), rhs.clone()))
-        .collect::<HashMap<_, _>>();
-
-    let cycle_data = lines
-        .into_iter()
-        .filter_map(|Line { lhs, rhs: _ }| lhs.ends_with('A').then_some(lhs.as_str()))
-        .map(|start| {
-            let mut rls = rls
-                .iter()
-                .enumerate()
-                .cycle()
-                .enumerate()
-                .map(|(n, x)| (n + 1, x));
-            let mut seen = HashMap::new();
-            let mut node = start;
-
-            loop {
-                let (step, rl) = rls.next().unwrap();
-                let (l, r) = linesmap.get(node).unwrap();
-                node = match rl.1 {
-                    RL::R => r.as_str(),
-                    RL::L => l.as_str(),
+impl Network {
+    pub fn new(input: &Input) -> Result<Self> {
+        let Input { rls, lines } = input;
+        ensure!(!rls.is_empty(), "no instructions");
+        let mut ids = HashMap::new();
+        for (id, Line { lhs, .. }) in lines.iter().enumerate() {
+            if ids.insert(lhs.clone(), id).is_some() {
+                bail!("node {lhs} is defined twice");
+            }
+        }
+        let next = lines
+            .iter()
+            .map(|Line { lhs, rhs: (l, r) }| {
+                let lookup = |name: &String| {
+                    ids.get(name).copied().with_context(|| {
+                        format!("node {name} is referenced by {lhs} but never defined")
+                    })
                 };
+                Ok((lookup(l)?, lookup(r)?))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Network {
+            rls: rls.clone(),
+            names: lines.iter().map(|line| line.lhs.clone()).collect(),
+            ids,
+            next,
+        })
+    }
+
+    pub fn id(&self, name: &str) -> Result<NodeId> {
+        self.ids
+            .get(name)
+            .copied()
+            .with_context(|| format!("node {name} is not defined"))
+    }
 
-                if let Some(prev_step) = seen.insert((node, rl.0), step) {
-                    seen.insert((node, rl.0), prev_step);
-                    return CycleData {
-                        seen_zs: seen
-                            .into_iter()
-                            .filter_map(|((k, _), v)| k.ends_with('Z').then(|| (k.to_string(), v)))
-                            .collect(),
-                    };
+    pub fn name(&self, id: NodeId) -> &str {
+        &self.names[id]
+    }
+
+    pub fn nodes(&self) -> impl Iterator<Item = NodeId> {
+        0..self.names.len()
+    }
+
+    pub fn successor(&self, (node, i): State) -> State {
+        let (l, r) = self.next[node];
+        let node = match self.rls[i] {
+            RL::R => r,
+            RL::L => l,
+        };
+        (node, (i + 1) % self.rls.len())
+    }
+
+    // Every state with an edge to its successor: a functional graph of nodes × instructions.
+    pub fn state_graph(&self) -> DiGraphMap<State, ()> {
+        let mut graph = DiGraphMap::new();
+        for state in self.nodes().cartesian_product(0..self.rls.len()) {
+            graph.add_edge(state, self.successor(state), ());
+        }
+        graph
+    }
+
+    // The states visited from `start` up to (not including) the first repeat.
+    pub fn orbit(&self, start: State) -> Vec<State> {
+        let mut seen = HashSet::new();
+        successors(Some(start), |s| Some(self.successor(*s)))
+            .take_while(|s| seen.insert(*s))
+            .collect()
+    }
+
+    pub fn steps_until(&self, start: NodeId, is_target: impl Fn(NodeId) -> bool) -> Result<usize> {
+        self.orbit((start, 0))
+            .into_iter()
+            .position(|(node, _)| is_target(node))
+            .with_context(|| format!("no target is reachable from {}", self.name(start)))
+    }
+
+    // Targets the walk from `start` never visits.
+    pub fn unreachable_targets(
+        &self,
+        start: NodeId,
+        is_target: impl Fn(NodeId) -> bool,
+    ) -> Vec<NodeId> {
+        let visited = self
+            .orbit((start, 0))
+            .into_iter()
+            .map(|(node, _)| node)
+            .collect::<HashSet<_>>();
+        self.nodes()
+            .filter(|n| is_target(*n) && !visited.contains(n))
+            .collect()
+    }
+
+    // Nodes that cannot reach any target whatever the instructions say.
+    pub fn dead_ends(&self, is_target: impl Fn(NodeId) -> bool) -> Vec<NodeId> {
+        let mut predecessors = vec![Vec::new(); self.names.len()];
+        for (node, (l, r)) in self.next.iter().enumerate() {
+            predecessors[*l].push(node);
+            predecessors[*r].push(node);
+        }
+        let mut live = self
+            .nodes()
+            .filter(|n| is_target(*n))
+            .collect::<HashSet<_>>();
+        let mut stack = live.iter().copied().collect_vec();
+        while let Some(node) = stack.pop() {
+            for &p in &predecessors[node] {
+                if live.insert(p) {
+                    stack.push(p);
                 }
             }
-        })
-        .collect::<Vec<_>>();
-    cycle_data
+        }
+        self.nodes().filter(|n| !live.contains(n)).collect()
+    }
+}
+
+#[aoc(day8, part1)]
+fn part1(input: &Input) -> Result<usize> {
+    let network = Network::new(input)?;
+    let target = network.id("ZZZ")?;
+    network.steps_until(network.id("AAA")?, |node| node == target)
+}
+
+// The steps at which a ghost stands on a Z node, when they are exactly first, first + d,
+// first + 2d, ... with d dividing the period of the ghost's walk; returns (first, d). The walk's
+// history covers every state of its cycle, so checking one period's worth of steps is enough.
+fn z_steps(network: &Network, start: NodeId) -> Result<(usize, usize)> {
+    let history = History::detect((start, 0), |state| network.successor(*state));
+    let Cycle { prefix, period } = history.cycle();
+    let end = prefix.max(1) + period;
+    let zs = (1..end)
+        .filter(|step| network.name(history.nth(*step).0).ends_with('Z'))
+        .collect_vec();
+    let name = network.name(start);
+    let first = *zs
+        .first()
+        .with_context(|| format!("the ghost from {name} never reaches a Z node"))?;
+    let d = zs.get(1).map_or(period, |second| second - first);
+    ensure!(
+        period % d == 0 && zs == (first..end).step_by(d).collect_vec(),
+        "the ghost from {name} does not reach Z nodes at regular intervals \
+        (prefix {prefix}, period {period}, Z at {zs:?})"
+    );
+    Ok((first, d))
+}
+
+#[aoc(day8, part2)]
+fn part2(input: &Input) -> Result<usize> {
+    let network = Network::new(input)?;
+    let ghosts = network
+        .nodes()
+        .filter(|node| network.name(*node).ends_with('A'))
+        .map(|start| z_steps(&network, start))
+        .collect::<Result<Vec<_>>>()?;
+    ensure!(!ghosts.is_empty(), "no ghost start nodes");
+    // Ghost i is on a Z node exactly at the steps t >= first_i with t = first_i (mod d_i), so
+    // the answer is the first step past every ghost's first arrival that solves all of those
+    // congruences at once.
+    let (residue, modulus) = ghosts
         .iter()
-        .map(|CycleData { seen_zs }| seen_zs.values().copied().next().unwrap())
-        .fold(1usize, |x, y| lcm(x, y))
+        .map(|&(first, d)| (first as i128, d as i128))
+        .try_fold((0, 1), crt)
+        .context("the ghosts are never all on Z nodes at once")?;
+    let latest = ghosts.iter().map(|(first, _)| *first).max().unwrap() as i128;
+    Ok(usize::try_from(
+        latest + (residue - latest).rem_euclid(modulus),
+    )?)
+}
+
+// Combines t = a1 (mod m1) and t = a2 (mod m2) into t = a (mod lcm(m1, m2)), for moduli that
+// need not be coprime. None if the two have no common solution.
+fn crt((a1, m1): (i128, i128), (a2, m2): (i128, i128)) -> Option<(i128, i128)> {
+    let ExtendedGcd { gcd, x, .. } = m1.extended_gcd(&m2);
+    if (a2 - a1) % gcd != 0 {
+        return None;
+    }
+    // x * m1 = gcd (mod m2), so stepping a1 by k * m1 with k = x * (a2 - a1) / gcd reaches a2.
+    let m2 = m2 / gcd;
+    let k = ((a2 - a1) / gcd % m2 * (x % m2)).rem_euclid(m2);
+    let modulus = m1 * m2;
+    Some(((a1 + m1 * k).rem_euclid(modulus), modulus))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn samples() {
+        let input = parse(
+            "LLR
+
+AAA = (BBB, BBB)
+BBB = (AAA, ZZZ)
+ZZZ = (ZZZ, ZZZ)
+",
+        )
+        .unwrap();
+        assert_eq!(part1(&input).unwrap(), 6);
+
+        let input = parse(
+            "LR
+
+11A = (11B, XXX)
+11B = (XXX, 11Z)
+11Z = (11B, XXX)
+22A = (22B, XXX)
+22B = (22C, 22C)
+22C = (22Z, 22Z)
+22Z = (22B, 22B)
+XXX = (XXX, XXX)
+",
+        )
+        .unwrap();
+        assert_eq!(part2(&input).unwrap(), 6);
+        let network = Network::new(&input).unwrap();
+        let dead_ends = network.dead_ends(|n| network.name(n).ends_with('Z'));
+        assert_eq!(dead_ends, [network.id("XXX").unwrap()]);
+        assert_eq!(network.state_graph().node_count(), 16);
+    }
+
+    #[test]
+    fn bad_networks_are_errors() {
+        let unreachable = parse("L\n\nAAA = (AAA, ZZZ)\nZZZ = (ZZZ, ZZZ)\n").unwrap();
+        assert!(part1(&unreachable).is_err());
+        let network = Network::new(&unreachable).unwrap();
+        let zzz = network.id("ZZZ").unwrap();
+        assert_eq!(network.unreachable_targets(0, |n| n == zzz), [zzz]);
+
+        let undefined = parse("L\n\nAAA = (BBB, ZZZ)\nZZZ = (ZZZ, ZZZ)\n").unwrap();
+        assert!(Network::new(&undefined).is_err());
+    }
+
+    #[test]
+    fn bad_inputs_are_errors() {
+        assert!(parse("LX\n\nAAA = (AAA, AAA)\n").is_err());
+        assert!(parse("L\nAAA = (AAA, AAA)\n").is_err());
+        assert!(parse("L\n\nAAA = AAA, AAA\n").is_err());
+        assert!(parse("L\n\nAAA (AAA, AAA)\n").is_err());
+
+        let no_ghosts = parse("L\n\nBBB = (ZZZ, ZZZ)\nZZZ = (ZZZ, ZZZ)\n").unwrap();
+        assert!(part2(&no_ghosts).is_err());
+
+        // Stops at Z once, then never again.
+        let once = parse("L\n\n11A = (11Z, 11Z)\n11Z = (11B, 11B)\n11B = (11B, 11B)\n").unwrap();
+        assert!(part2(&once).is_err());
+        // Z at steps 1, 2, 5, 6, ... is no single arithmetic progression.
+        let irregular =
+            parse("L\n\n11A = (11Z, 11Z)\n11Z = (12Z, 12Z)\n12Z = (11B, 11B)\n11B = (11A, 11A)\n")
+                .unwrap();
+        assert!(part2(&irregular).is_err());
+
+        // Z at 1, 3, 5, ... and at 2, 4, 6, ... never line up.
+        let apart = parse(
+            "L\n\n11A = (11Z, 11Z)\n11Z = (11B, 11B)\n11B = (11Z, 11Z)\n\
+            22A = (22B, 22B)\n22B = (22Z, 22Z)\n22Z = (22B, 22B)\n",
+        )
+        .unwrap();
+        assert!(part2(&apart).is_err());
+    }
+
+    #[test]
+    fn offset_cycles() {
+        // Z at 3, 8, 13, ... and at 2, 4, 6, ... first meet at 8.
+        let input = parse(
+            "L
+
+11A = (11B, 11B)
+11B = (11C, 11C)
+11C = (11Z, 11Z)
+11Z = (11D, 11D)
+11D = (11E, 11E)
+11E = (11F, 11F)
+11F = (11G, 11G)
+11G = (11Z, 11Z)
+22A = (22B, 22B)
+22B = (22Z, 22Z)
+22Z = (22B, 22B)
+",
+        )
+        .unwrap();
+        assert_eq!(part2(&input).unwrap(), 8);
+        assert_eq!(crt((3, 5), (0, 2)), Some((8, 10)));
+        assert_eq!(crt((1, 4), (3, 6)), Some((9, 12)));
+        assert_eq!(crt((1, 4), (2, 6)), None);
+    }
 }
@@ -1,5 +1,7 @@
+use anyhow::{ensure, Result};
 use aoc_runner_derive::{aoc, aoc_generator};
 use itertools::Itertools;
+use num::{BigInt, One, Zero};
 #[aoc_generator(day9)]
 fn parse(input: &str) -> Vec<Vec<i64>> {
     input
@@ -12,59 +14,86 @@ fn parse(input: &str) -> Vec<Vec<i64>> {
         .collect()
 }
 
-#[aoc(day9, part1)]
-fn part1(input: &[Vec<i64>]) -> i64 {
-    input
-        .into_iter()
-        .map(|line| {
-            let mut lines = vec![line.clone()];
-            while lines.last().unwrap().iter().any(|n| *n != 0) {
-                let line = lines.last().unwrap();
-                lines.push(
-                    line.into_iter()
-                        .tuple_windows::<(_, _)>()
-                        .map(|(a, b)| b - a)
-                        .collect(),
-                );
-            }
-            lines.reverse();
+// The exact polynomial through a sequence, in Newton forward-difference form:
+// p(x) = sum over k of (k-th difference at 0) * C(x, k), with x = 0 at the first reading.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Extrapolator {
+    newton: Vec<BigInt>,
+}
+
+impl Extrapolator {
+    pub fn fit(sequence: &[i64]) -> Result<Self> {
+        let mut row = sequence.iter().copied().map(BigInt::from).collect_vec();
+        let mut newton = Vec::new();
+        while row.iter().any(|n| !n.is_zero()) {
+            newton.push(row[0].clone());
+            row = row.iter().tuple_windows().map(|(a, b)| b - a).collect();
+        }
+        ensure!(
+            !row.is_empty(),
+            "{sequence:?} never reaches an all-zero difference row"
+        );
+        Ok(Extrapolator { newton })
+    }
 
-            lines[0].push(0);
-            for i in 1..lines.len() {
-                let delta = *lines[i - 1].last().unwrap();
-                let next = *lines[i].last().unwrap() + delta;
-                lines[i].push(next);
+    // The zero polynomial counts as degree 0.
+    pub fn degree(&self) -> usize {
+        self.newton.len().saturating_sub(1)
+    }
+
+    pub fn eval(&self, x: &BigInt) -> BigInt {
+        let mut binomial = BigInt::one();
+        let mut sum = BigInt::zero();
+        for (k, coeff) in self.newton.iter().enumerate() {
+            if k > 0 {
+                // C(x, k) = C(x, k - 1) * (x - k + 1) / k, and the division is always exact.
+                binomial = binomial * (x - (k - 1)) / k;
             }
-            *lines.last().unwrap().last().unwrap()
-        })
+            sum += coeff * &binomial;
+        }
+        sum
+    }
+
+    pub fn eval_at(&self, x: i64) -> BigInt {
+        self.eval(&BigInt::from(x))
+    }
+}
+
+#[aoc(day9, part1)]
+fn part1(input: &[Vec<i64>]) -> Result<BigInt> {
+    input
+        .iter()
+        .map(|line| Ok(Extrapolator::fit(line)?.eval_at(line.len() as i64)))
         .sum()
 }
 
 #[aoc(day9, part2)]
-fn part2(input: &[Vec<i64>]) -> i64 {
+fn part2(input: &[Vec<i64>]) -> Result<BigInt> {
     input
-        .into_iter()
-        .map(|line| {
-            let mut lines = vec![line.clone()];
-            while lines.last().unwrap().iter().any(|n| *n != 0) {
-                let line = lines.last().unwrap();
-                lines.push(
-                    line.into_iter()
-                        .tuple_windows::<(_, _)>()
-                        .map(|(a, b)| b - a)
-                        .collect(),
-                );
-            }
-            lines.reverse();
-            lines.iter_mut().for_each(|line| line.reverse());
-
-            lines[0].push(0);
-            for i in 1..lines.len() {
-                let delta = *lines[i - 1].last().unwrap();
-                let next = *lines[i].last().unwrap() - delta;
-                lines[i].push(next);
-            }
-            *lines.last().unwrap().last().unwrap()
-        })
+        .iter()
+        .map(|line| Ok(Extrapolator::fit(line)?.eval_at(-1)))
         .sum()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sample() {
+        let input = parse("0 3 6 9 12 15\n1 3 6 10 15 21\n10 13 16 21 30 45\n");
+        assert_eq!(part1(&input).unwrap(), BigInt::from(114));
+        assert_eq!(part2(&input).unwrap(), BigInt::from(2));
+    }
+
+    #[test]
+    fn degree_and_far_offsets() {
+        let triangular = Extrapolator::fit(&[1, 3, 6, 10, 15, 21]).unwrap();
+        assert_eq!(triangular.degree(), 2);
+        // The n-th reading is (n + 1)(n + 2) / 2.
+        let n = BigInt::from(10).pow(30);
+        assert_eq!(triangular.eval(&n), (&n + 1) * (&n + 2) / 2);
+
+        assert!(Extrapolator::fit(&[1, 2, 4, 8, 16]).is_err());
+    }
+}
@@ -0,0 +1,291 @@
+use itertools::Itertools;
+
+// Half-open [start, end). Anything with start >= end is empty.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Interval {
+    pub start: i64,
+    pub end: i64,
+}
+
+impl Interval {
+    pub const ALL: Interval = Interval {
+        start: i64::MIN,
+        end: i64::MAX,
+    };
+
+    pub fn new(start: i64, end: i64) -> Self {
+        Interval { start, end }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start >= self.end
+    }
+
+    pub fn len(&self) -> u64 {
+        if self.is_empty() {
+            0
+        } else {
+            self.end.abs_diff(self.start)
+        }
+    }
+
+    pub fn contains(&self, x: i64) -> bool {
+        self.start <= x && x < self.end
+    }
+
+    pub fn intersect(&self, other: &Interval) -> Option<Interval> {
+        Some(Interval::new(
+            self.start.max(other.start),
+            self.end.min(other.end),
+        ))
+        .filter(|i| !i.is_empty())
+    }
+
+    // [start, at) and [at, end), each dropped if empty.
+    pub fn split_at(&self, at: i64) -> (Option<Interval>, Option<Interval>) {
+        let lo = Interval::new(self.start, self.end.min(at));
+        let hi = Interval::new(self.start.max(at), self.end);
+        (
+            Some(lo).filter(|i| !i.is_empty()),
+            Some(hi).filter(|i| !i.is_empty()),
+        )
+    }
+
+    // Saturates, so the ends of `Interval::ALL` stay where they are.
+    pub fn shift(&self, by: i64) -> Interval {
+        Interval::new(self.start.saturating_add(by), self.end.saturating_add(by))
+    }
+}
+
+// Sorted, disjoint, non-adjacent, non-empty intervals.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct IntervalSet {
+    intervals: Vec<Interval>,
+}
+
+impl FromIterator<Interval> for IntervalSet {
+    fn from_iter<T: IntoIterator<Item = Interval>>(iter: T) -> Self {
+        let intervals = iter
+            .into_iter()
+            .filter(|i| !i.is_empty())
+            .sorted()
+            .coalesce(|a, b| {
+                if b.start <= a.end {
+                    Ok(Interval::new(a.start, a.end.max(b.end)))
+                } else {
+                    Err((a, b))
+                }
+            })
+            .collect();
+        IntervalSet { intervals }
+    }
+}
+
+impl From<Interval> for IntervalSet {
+    fn from(interval: Interval) -> Self {
+        std::iter::once(interval).collect()
+    }
+}
+
+impl IntervalSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn intervals(&self) -> &[Interval] {
+        &self.intervals
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.intervals.is_empty()
+    }
+
+    // Total number of integers covered.
+    pub fn len(&self) -> u64 {
+        self.intervals.iter().map(Interval::len).sum()
+    }
+
+    pub fn min(&self) -> Option<i64> {
+        self.intervals.first().map(|i| i.start)
+    }
+
+    pub fn contains(&self, x: i64) -> bool {
+        let i = self.intervals.partition_point(|i| i.end <= x);
+        self.intervals.get(i).is_some_and(|i| i.contains(x))
+    }
+
+    pub fn union(&self, other: &IntervalSet) -> IntervalSet {
+        self.intervals
+            .iter()
+            .chain(&other.intervals)
+            .copied()
+            .collect()
+    }
+
+    pub fn intersection(&self, other: &IntervalSet) -> IntervalSet {
+        let mut out = Vec::new();
+        let (mut i, mut j) = (0, 0);
+        while i < self.intervals.len() && j < other.intervals.len() {
+            let (a, b) = (self.intervals[i], other.intervals[j]);
+            out.extend(a.intersect(&b));
+            if a.end < b.end {
+                i += 1;
+            } else {
+                j += 1;
+            }
+        }
+        IntervalSet { intervals: out }
+    }
+
+    pub fn complement(&self) -> IntervalSet {
+        let starts = std::iter::once(i64::MIN).chain(self.intervals.iter().map(|i| i.end));
+        let ends = self
+            .intervals
+            .iter()
+            .map(|i| i.start)
+            .chain(std::iter::once(i64::MAX));
+        starts
+            .zip(ends)
+            .map(|(start, end)| Interval::new(start, end))
+            .collect()
+    }
+
+    pub fn difference(&self, other: &IntervalSet) -> IntervalSet {
+        self.intersection(&other.complement())
+    }
+
+    // Everything below `at`, and everything from `at` up.
+    pub fn split_at(&self, at: i64) -> (IntervalSet, IntervalSet) {
+        let (lo, hi) = Interval::ALL.split_at(at);
+        (
+            self.intersection(&lo.map(IntervalSet::from).unwrap_or_default()),
+            self.intersection(&hi.map(IntervalSet::from).unwrap_or_default()),
+        )
+    }
+}
+
+// An axis-aligned box of N half-open intervals.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct IntervalBox<const N: usize> {
+    pub dims: [Interval; N],
+}
+
+impl<const N: usize> IntervalBox<N> {
+    pub fn new(dims: [Interval; N]) -> Self {
+        IntervalBox { dims }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.dims.iter().any(Interval::is_empty)
+    }
+
+    pub fn volume(&self) -> u64 {
+        self.dims.iter().map(Interval::len).product()
+    }
+
+    pub fn contains(&self, point: [i64; N]) -> bool {
+        self.dims.iter().zip(point).all(|(d, x)| d.contains(x))
+    }
+
+    pub fn intersect(&self, other: &Self) -> Option<Self> {
+        let mut dims = self.dims;
+        for (d, o) in dims.iter_mut().zip(&other.dims) {
+            *d = d.intersect(o)?;
+        }
+        Some(IntervalBox { dims })
+    }
+
+    // Splits along axis `dim`, like `Interval::split_at`.
+    pub fn split_at(&self, dim: usize, at: i64) -> (Option<Self>, Option<Self>) {
+        let (lo, hi) = self.dims[dim].split_at(at);
+        let with = |interval: Interval| {
+            let mut dims = self.dims;
+            dims[dim] = interval;
+            IntervalBox { dims }
+        };
+        (lo.map(with), hi.map(with))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Deterministic xorshift so the properties below are checked on the same cases every run.
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn range(&mut self, lo: i64, hi: i64) -> i64 {
+            lo + (self.next() % (hi - lo) as u64) as i64
+        }
+
+        fn interval(&mut self) -> Interval {
+            let start = self.range(-20, 20);
+            Interval::new(start, start + self.range(-2, 10))
+        }
+
+        fn set(&mut self) -> IntervalSet {
+            let n = self.range(0, 5);
+            (0..n).map(|_| self.interval()).collect()
+        }
+    }
+
+    fn is_normalized(set: &IntervalSet) -> bool {
+        set.intervals().iter().all(|i| !i.is_empty())
+            && set
+                .intervals()
+                .iter()
+                .tuple_windows()
+                .all(|(a, b)| a.end < b.start)
+    }
+
+    #[test]
+    fn set_operations_match_membership() {
+        let mut rng = Rng(0x2023_1205);
+        for _ in 0..500 {
+            let (a, b) = (rng.set(), rng.set());
+            let at = rng.range(-25, 25);
+            let union = a.union(&b);
+            let intersection = a.intersection(&b);
+            let difference = a.difference(&b);
+            let (below, above) = a.split_at(at);
+            for set in [&union, &intersection, &difference, &below, &above] {
+                assert!(is_normalized(set), "{set:?}");
+            }
+            for x in -30..30 {
+                let (in_a, in_b) = (a.contains(x), b.contains(x));
+                assert_eq!(union.contains(x), in_a || in_b);
+                assert_eq!(intersection.contains(x), in_a && in_b);
+                assert_eq!(difference.contains(x), in_a && !in_b);
+                assert_eq!(below.contains(x), in_a && x < at);
+                assert_eq!(above.contains(x), in_a && x >= at);
+            }
+            assert_eq!(below.len() + above.len(), a.len());
+            assert_eq!(a.complement().complement(), a);
+        }
+    }
+
+    #[test]
+    fn box_splits_preserve_volume() {
+        let mut rng = Rng(0x1919);
+        for _ in 0..500 {
+            let b = IntervalBox::new([rng.interval(), rng.interval(), rng.interval()]);
+            let dim = rng.range(0, 3) as usize;
+            let at = rng.range(-25, 25);
+            let (lo, hi) = b.split_at(dim, at);
+            let volume = |b: Option<IntervalBox<3>>| b.map_or(0, |b| b.volume());
+            assert_eq!(volume(lo) + volume(hi), b.volume());
+            let point = [rng.range(-20, 30), rng.range(-20, 30), rng.range(-20, 30)];
+            let in_parts =
+                lo.is_some_and(|b| b.contains(point)) || hi.is_some_and(|b| b.contains(point));
+            assert_eq!(in_parts, b.contains(point));
+        }
+    }
+}
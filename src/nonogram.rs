@@ -0,0 +1,206 @@
+use anyhow::{anyhow, ensure, Result};
+use itertools::Itertools;
+
+use crate::day12::{render, Line, Spring};
+
+pub type Grid = Vec<Vec<Spring>>;
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Solutions {
+    None,
+    Unique(Grid),
+    // Two distinct solutions; there may be more.
+    Multiple(Grid, Grid),
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Nonogram {
+    rows: Vec<Vec<usize>>,
+    cols: Vec<Vec<usize>>,
+}
+
+// `None` is a cell not yet decided.
+type Partial = Vec<Vec<Option<Spring>>>;
+
+impl Nonogram {
+    pub fn new(rows: Vec<Vec<usize>>, cols: Vec<Vec<usize>>) -> Result<Self> {
+        let (filled_rows, filled_cols) = (
+            rows.iter().flatten().sum::<usize>(),
+            cols.iter().flatten().sum::<usize>(),
+        );
+        ensure!(
+            filled_rows == filled_cols,
+            "row clues fill {filled_rows} cells but column clues fill {filled_cols}"
+        );
+        Ok(Nonogram { rows, cols })
+    }
+
+    // One clue per line, e.g. "1,1", or "0" for an empty line, with rows and columns separated
+    // by a blank line.
+    pub fn parse(input: &str) -> Result<Self> {
+        let (rows, cols) = input
+            .split_once("\n\n")
+            .ok_or_else(|| anyhow!("no blank line between row and column clues"))?;
+        let clues = |s: &str| -> Result<Vec<Vec<usize>>> {
+            s.lines()
+                .map(|line| {
+                    if line.trim() == "0" {
+                        return Ok(Vec::new());
+                    }
+                    line.split(',')
+                        .map(|n| {
+                            let n = n.trim().parse()?;
+                            ensure!(n > 0, "{line:?} mixes 0 with other clues");
+                            Ok(n)
+                        })
+                        .collect()
+                })
+                .collect()
+        };
+        Self::new(clues(rows)?, clues(cols.trim_end())?)
+    }
+
+    pub fn height(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn width(&self) -> usize {
+        self.cols.len()
+    }
+
+    // Runs the day12 line count over one row or column and narrows it to the cells that agree
+    // across every arrangement. Returns whether anything changed, or None on a contradiction.
+    fn settle_line(cells: &mut [Option<Spring>], sizes: &[usize]) -> Option<bool> {
+        let line = Line {
+            row: cells.to_vec(),
+            sizes: sizes.to_vec(),
+        };
+        let forced = line.forced()?;
+        let mut changed = false;
+        for (cell, forced) in cells.iter_mut().zip(forced) {
+            if cell.is_none() && forced.is_some() {
+                *cell = forced;
+                changed = true;
+            }
+        }
+        Some(changed)
+    }
+
+    // Line-wise propagation to a fixed point. Returns false on a contradiction.
+    fn propagate(&self, grid: &mut Partial) -> bool {
+        loop {
+            let mut changed = false;
+            for (r, sizes) in self.rows.iter().enumerate() {
+                match Self::settle_line(&mut grid[r], sizes) {
+                    None => return false,
+                    Some(c) => changed |= c,
+                }
+            }
+            for (c, sizes) in self.cols.iter().enumerate() {
+                let mut column = grid.iter().map(|row| row[c]).collect_vec();
+                match Self::settle_line(&mut column, sizes) {
+                    None => return false,
+                    Some(ch) => changed |= ch,
+                }
+                for (row, cell) in grid.iter_mut().zip(column) {
+                    row[c] = cell;
+                }
+            }
+            if !changed {
+                return true;
+            }
+        }
+    }
+
+    // Depth-first search over the first undecided cell, stopping once `limit` solutions are
+    // found.
+    fn search(&self, mut grid: Partial, limit: usize, found: &mut Vec<Grid>) {
+        if found.len() >= limit || !self.propagate(&mut grid) {
+            return;
+        }
+        let undecided = (0..self.height())
+            .cartesian_product(0..self.width())
+            .find(|&(r, c)| grid[r][c].is_none());
+        match undecided {
+            None => found.push(
+                grid.into_iter()
+                    .map(|row| row.into_iter().map(Option::unwrap).collect())
+                    .collect(),
+            ),
+            Some((r, c)) => {
+                for guess in [Spring::Damaged, Spring::Fine] {
+                    let mut next = grid.clone();
+                    next[r][c] = Some(guess);
+                    self.search(next, limit, found);
+                }
+            }
+        }
+    }
+
+    pub fn solve(&self) -> Solutions {
+        let mut found = Vec::new();
+        self.search(vec![vec![None; self.width()]; self.height()], 2, &mut found);
+        let mut found = found.into_iter();
+        match (found.next(), found.next()) {
+            (None, _) => Solutions::None,
+            (Some(grid), None) => Solutions::Unique(grid),
+            (Some(a), Some(b)) => Solutions::Multiple(a, b),
+        }
+    }
+}
+
+pub fn render_grid(grid: &Grid) -> String {
+    grid.iter().map(|row| render(row) + "\n").collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unique() {
+        // A 5x5 letter P.
+        let puzzle = Nonogram::parse("4\n1,1\n4\n1\n1\n\n5\n1,1\n1,1\n3\n0\n").unwrap();
+        let Solutions::Unique(grid) = puzzle.solve() else {
+            panic!("{:?}", puzzle.solve());
+        };
+        assert_eq!(
+            render_grid(&grid),
+            "####.
+#..#.
+####.
+#....
+#....
+"
+        );
+    }
+
+    #[test]
+    fn multiple_and_none() {
+        // Either diagonal of a 2x2 grid.
+        let diagonal = Nonogram::new(vec![vec![1], vec![1]], vec![vec![1], vec![1]]).unwrap();
+        match diagonal.solve() {
+            Solutions::Multiple(a, b) => assert_ne!(a, b),
+            other => panic!("{other:?}"),
+        }
+
+        let impossible = Nonogram::new(vec![vec![2], vec![]], vec![vec![1, 1], vec![]]).unwrap();
+        assert_eq!(impossible.solve(), Solutions::None);
+        assert!(Nonogram::new(vec![vec![1]], vec![vec![]]).is_err());
+    }
+
+    #[test]
+    fn empty_clues() {
+        let err = Nonogram::parse("1\n0\n\n1,0\n").unwrap_err();
+        assert!(err.to_string().contains("mixes 0"), "{err}");
+        assert!(Nonogram::parse("1\n\n1\n\n1\n").is_err());
+
+        // An empty middle row splits the column clues.
+        let puzzle = Nonogram::parse("1\n0\n1\n\n1,1\n").unwrap();
+        assert_eq!(puzzle.height(), 3);
+        let Solutions::Unique(grid) = puzzle.solve() else {
+            panic!("{:?}", puzzle.solve());
+        };
+        assert_eq!(render_grid(&grid), "#\n.\n#\n");
+    }
+}